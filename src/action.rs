@@ -0,0 +1,187 @@
+use crate::net::{
+    TicCmd, BTS_PAUSE, BTS_SAVEGAME, BTS_SAVEMASK, BTS_SAVESHIFT, BT_ATTACK, BT_CHANGE, BT_SPECIAL,
+    BT_USE, BT_WEAPONMASK, BT_WEAPONSHIFT,
+};
+
+// Movement speeds from the game's key handling, indexed by the run modifier.
+const FORWARD_MOVE: [i8; 2] = [0x19, 0x32];
+const SIDE_MOVE: [i8; 2] = [0x18, 0x28];
+
+/// Weapons, numbered the way `BT_CHANGE` expects them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weapon {
+    /// The fist, or the berserk fist.
+    Fist,
+    /// The pistol.
+    Pistol,
+    /// The shotgun.
+    Shotgun,
+    /// The chaingun.
+    Chaingun,
+    /// The rocket launcher.
+    RocketLauncher,
+    /// The plasma rifle.
+    PlasmaRifle,
+    /// The BFG 9000.
+    Bfg,
+    /// The chainsaw.
+    Chainsaw,
+    /// The super shotgun, in Doom II.
+    SuperShotgun,
+}
+
+impl Weapon {
+    // The super shotgun shares the shotgun's slot, and the game picks
+    // between them itself.
+    pub(crate) fn slot(self) -> u8 {
+        match self {
+            Weapon::Fist => 0,
+            Weapon::Pistol => 1,
+            Weapon::Shotgun | Weapon::SuperShotgun => 2,
+            Weapon::Chaingun => 3,
+            Weapon::RocketLauncher => 4,
+            Weapon::PlasmaRifle => 5,
+            Weapon::Bfg => 6,
+            Weapon::Chainsaw => 7,
+        }
+    }
+}
+
+/// Commands that replace the player's buttons for a tic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Special {
+    /// Pauses or unpauses the game.
+    Pause,
+    /// Saves the game in the given slot.
+    SaveGame(u8),
+}
+
+/// How the player turns on a tic.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Turn {
+    /// Keeps facing the same way.
+    #[default]
+    None,
+    /// Turns by the given number of degrees, counter-clockwise.
+    By(f32),
+    /// Turns to face the given angle in degrees, counter-clockwise from the
+    /// direction the player was facing when the compiler was created.
+    To(f32),
+}
+
+/// What the player should do on one tic.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Intent {
+    /// Forward speed from -1.0 (full speed backwards) to 1.0.
+    pub forward: f32,
+    /// Strafing speed from -1.0 (full speed left) to 1.0.
+    pub strafe: f32,
+    /// Moves at running instead of walking speed.
+    pub run: bool,
+    /// Where to turn.
+    pub turn: Turn,
+    /// Fires the weapon.
+    pub fire: bool,
+    /// Presses the use key, opening doors and flipping switches.
+    pub activate: bool,
+    /// Switches to this weapon.
+    pub weapon: Option<Weapon>,
+    /// Overrides every button above, as the game ignores them for the tic.
+    pub special: Option<Special>,
+}
+
+/// Turns intents into ticcmds.
+///
+/// It keeps track of where the player is facing from the turns it made, and
+/// when the game runs with `lowres_turn` it carries the rounding error of
+/// each turn over to the next one, like the game does for its own input.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ActionCompiler {
+    lowres_turn: bool,
+    heading: u16,
+    carry: i16,
+}
+
+impl ActionCompiler {
+    /// A compiler facing the way the player faces now, for games with or without `lowres_turn`.
+    pub fn new(lowres_turn: bool) -> Self {
+        ActionCompiler {
+            lowres_turn,
+            ..Default::default()
+        }
+    }
+
+    /// Where the player is facing, in degrees, counter-clockwise.
+    pub fn get_heading(&self) -> f32 {
+        self.heading as f32 / 65536.0 * 360.0
+    }
+
+    /// Turns what the player wants to do on this tic into its command.
+    pub fn compile(&mut self, intent: &Intent) -> TicCmd {
+        let run = intent.run as usize;
+
+        TicCmd {
+            forwardmove: Self::scale(intent.forward, FORWARD_MOVE[run]),
+            // Positive sidemove strafes right.
+            sidemove: Self::scale(intent.strafe, SIDE_MOVE[run]),
+            angleturn: self.compile_turn(intent.turn),
+            buttons: Self::compile_buttons(intent),
+            ..Default::default()
+        }
+    }
+
+    fn scale(speed: f32, max: i8) -> i8 {
+        (speed.clamp(-1.0, 1.0) * max as f32).round() as i8
+    }
+
+    fn compile_turn(&mut self, turn: Turn) -> i16 {
+        let desired = match turn {
+            Turn::None => 0,
+            Turn::By(degrees) => Self::from_degrees(degrees),
+            Turn::To(degrees) => Self::from_degrees(degrees).wrapping_sub(self.heading as i16),
+        };
+
+        let angleturn = if self.lowres_turn {
+            // Only the high byte reaches the server, so round to it and make
+            // up for the difference on the next tic.
+            let desired = desired.wrapping_add(self.carry);
+            let rounded = (desired.wrapping_add(128) as u16 & 0xff00) as i16;
+            self.carry = desired.wrapping_sub(rounded);
+            rounded
+        } else {
+            desired
+        };
+
+        self.heading = self.heading.wrapping_add(angleturn as u16);
+        angleturn
+    }
+
+    fn compile_buttons(intent: &Intent) -> u8 {
+        if let Some(special) = intent.special {
+            return BT_SPECIAL
+                | match special {
+                    Special::Pause => BTS_PAUSE,
+                    Special::SaveGame(slot) => {
+                        BTS_SAVEGAME | ((slot << BTS_SAVESHIFT) & BTS_SAVEMASK)
+                    }
+                };
+        }
+
+        let mut buttons = 0;
+        if intent.fire {
+            buttons |= BT_ATTACK;
+        }
+        if intent.activate {
+            buttons |= BT_USE;
+        }
+        if let Some(weapon) = intent.weapon {
+            buttons |= BT_CHANGE | ((weapon.slot() << BT_WEAPONSHIFT) & BT_WEAPONMASK);
+        }
+        buttons
+    }
+
+    // angleturn is the high half of a 32 bit angle, so a full turn is 65536.
+    fn from_degrees(degrees: f32) -> i16 {
+        (degrees.rem_euclid(360.0) / 360.0 * 65536.0).round() as i64 as u16 as i16
+    }
+}
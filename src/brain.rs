@@ -0,0 +1,105 @@
+use rand::prelude::*;
+
+use crate::action::{ActionCompiler, Intent, Turn};
+use crate::command::BotCommand;
+use crate::game::TICRATE;
+use crate::net::{ConsoleMessage, GameSettings, TicCmd, NET_MAXPLAYERS};
+
+/// Everything a brain gets to see for one tic.
+#[derive(Debug, Clone, Copy)]
+pub struct Observation<'a> {
+    /// The settings the game was started with.
+    pub settings: &'a GameSettings,
+    /// The tic the returned command will be sent as.
+    pub maketic: u32,
+    /// The number of tics the game has run so far.
+    pub gametic: u32,
+    /// The commands of every player for the last tic that was run.
+    pub cmds: &'a [TicCmd; NET_MAXPLAYERS],
+    /// Which players were in the game on the last tic that was run.
+    pub ingame: &'a [bool; NET_MAXPLAYERS],
+    /// What the server said on the console since the last command.
+    pub console: &'a [ConsoleMessage],
+}
+
+/// Decides what the local player does.
+///
+/// The game loop asks for exactly one command per tic it builds, and the
+/// client only transmits it, so a brain never has to deal with the network.
+pub trait Brain: Send {
+    /// Decides the command for the tic being built.
+    fn think(&mut self, observation: &Observation) -> TicCmd;
+
+    /// Takes an order a player gave over chat, returning the reply. Orders
+    /// left to the game loop, by returning `None`, are followed on top of
+    /// whatever the brain does where possible.
+    ///
+    /// Names to follow are spelled as in the lobby when someone there has
+    /// that name in any case, and left in the capitals of chat otherwise.
+    fn command(&mut self, _command: &BotCommand) -> Option<String> {
+        None
+    }
+}
+
+/// The brains that can be picked by name on the command line.
+pub const BRAIN_NAMES: &[&str] = &["idle", "forward", "wander"];
+
+/// Creates the brain with the given name, as listed in `BRAIN_NAMES`.
+pub fn from_name(name: &str) -> Option<Box<dyn Brain>> {
+    match name {
+        "idle" => Some(Box::new(IdleBrain)),
+        "forward" => Some(Box::new(ForwardBrain)),
+        "wander" => Some(Box::new(WanderBrain::default())),
+        _ => None,
+    }
+}
+
+/// Stands still.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdleBrain;
+
+impl Brain for IdleBrain {
+    fn think(&mut self, _observation: &Observation) -> TicCmd {
+        TicCmd::default()
+    }
+}
+
+/// Keeps running forward.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ForwardBrain;
+
+impl Brain for ForwardBrain {
+    fn think(&mut self, _observation: &Observation) -> TicCmd {
+        ActionCompiler::default().compile(&Intent {
+            forward: 1.0,
+            run: true,
+            ..Default::default()
+        })
+    }
+}
+
+/// Walks forward and faces a new random direction every second.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WanderBrain {
+    actions: Option<ActionCompiler>,
+}
+
+impl Brain for WanderBrain {
+    fn think(&mut self, observation: &Observation) -> TicCmd {
+        let actions = self
+            .actions
+            .get_or_insert_with(|| ActionCompiler::new(observation.settings.lowres_turn != 0));
+
+        let turn = if observation.maketic.is_multiple_of(TICRATE) {
+            Turn::To(rand::thread_rng().gen_range(0.0..360.0))
+        } else {
+            Turn::None
+        };
+
+        actions.compile(&Intent {
+            forward: 1.0,
+            turn,
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crate::net::NET_MAXPLAYERS;
+
+/// The `chatchar` that addresses a message to everyone. Lower values pick
+/// one player, numbered from 1.
+pub const HU_BROADCAST: u8 = 5;
+/// The `chatchar` that sends the line typed so far.
+pub const KEY_ENTER: u8 = 13;
+/// The `chatchar` that takes back the last character typed.
+pub const KEY_BACKSPACE: u8 = 0x7f;
+/// The longest line the game's chat input takes.
+pub const HU_MAXLINELENGTH: usize = 80;
+
+/// Who a chat message is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatDestination {
+    /// Every player.
+    Everyone,
+    /// A player number, from 0.
+    Player(usize),
+}
+
+impl ChatDestination {
+    fn from_chatchar(c: u8) -> Option<Self> {
+        match c {
+            HU_BROADCAST => Some(ChatDestination::Everyone),
+            1..HU_BROADCAST => Some(ChatDestination::Player(c as usize - 1)),
+            _ => None,
+        }
+    }
+
+    // The game can only address the first four players, so the others
+    // are reached through everyone.
+    fn to_chatchar(self) -> u8 {
+        match self {
+            ChatDestination::Player(player) if player + 1 < HU_BROADCAST as usize => {
+                player as u8 + 1
+            }
+            _ => HU_BROADCAST,
+        }
+    }
+}
+
+/// A line of chat, once its sender pressed enter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// The player number of the sender.
+    pub from: usize,
+    /// The sender's name, from the lobby.
+    pub name: String,
+    /// Who the message is for.
+    pub to: ChatDestination,
+    /// What was typed.
+    pub text: String,
+}
+
+impl ChatMessage {
+    /// Whether the game would show the message to `player`, which is `None`
+    /// for drones. Drones see everything, like the people behind them.
+    pub fn is_for(&self, player: Option<usize>) -> bool {
+        match (self.to, player) {
+            (ChatDestination::Player(to), Some(player)) => to == player,
+            _ => true,
+        }
+    }
+}
+
+/// Puts together the chat typed by each player, one `chatchar` per tic, the
+/// way the game's `HU_Ticker` does.
+#[derive(Debug, Default)]
+pub struct ChatAssembler {
+    destinations: [Option<ChatDestination>; NET_MAXPLAYERS],
+    lines: [String; NET_MAXPLAYERS],
+}
+
+impl ChatAssembler {
+    /// Takes the `chatchar` of a player's command for one tic. Returns the
+    /// destination and text of the line it finished, if any.
+    pub fn receive(&mut self, player: usize, c: u8) -> Option<(ChatDestination, String)> {
+        let line = &mut self.lines[player];
+        // The game shows lowercase as capitals.
+        match c.to_ascii_uppercase() {
+            0 => {}
+            1..=HU_BROADCAST => self.destinations[player] = ChatDestination::from_chatchar(c),
+            c @ b' '..=b'_' if line.len() < HU_MAXLINELENGTH => line.push(c as char),
+            KEY_BACKSPACE => {
+                line.pop();
+            }
+            KEY_ENTER => {
+                let text = std::mem::take(line);
+                // Lines without a destination are shown to nobody.
+                if !text.is_empty() {
+                    return self.destinations[player].map(|to| (to, text));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Chat waiting to go out, one `chatchar` per tic.
+#[derive(Debug, Default)]
+pub struct ChatQueue {
+    chars: VecDeque<u8>,
+}
+
+impl ChatQueue {
+    /// Queues a line of chat. The game only has capitals, and drops what it
+    /// cannot show, so the text is changed to match.
+    pub fn push(&mut self, to: ChatDestination, text: &str) {
+        self.chars.push_back(to.to_chatchar());
+        self.chars.extend(
+            text.chars()
+                .map(|c| c.to_ascii_uppercase())
+                .filter(|c| (' '..='_').contains(c))
+                .take(HU_MAXLINELENGTH)
+                .map(|c| c as u8),
+        );
+        self.chars.push_back(KEY_ENTER);
+    }
+
+    /// The `chatchar` to send with the next command, if any.
+    pub fn next_char(&mut self) -> Option<u8> {
+        self.chars.pop_front()
+    }
+
+    /// Whether everything queued has been sent.
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+}
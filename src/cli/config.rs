@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use hydra_bot::net::{ConnectData, ConnectDataBuilder, GameSettings, GameSettingsBuilder, Skill};
+use hydra_bot::{ClientConfig, ClientConfigBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Settings that can be given both on the command line and in a config file.
+/// The keys in the file are the names of the command line options.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub name: Option<String>,
+    pub player_class: Option<i32>,
+    pub max_players: Option<i32>,
+    pub lowres_turn: Option<bool>,
+    pub game_description: Option<String>,
+    /// In seconds.
+    pub connection_timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub join_retries: Option<u32>,
+    pub launch_players: Option<usize>,
+    pub episode: Option<i32>,
+    pub map: Option<i32>,
+    /// From 1 to 5, like Doom's `-skill`.
+    pub skill: Option<u8>,
+    pub deathmatch: Option<i32>,
+    /// In minutes.
+    pub timelimit: Option<u32>,
+    pub ticdup: Option<i32>,
+    pub extratics: Option<i32>,
+    pub nomonsters: Option<bool>,
+    pub fast: Option<bool>,
+    pub respawn: Option<bool>,
+}
+
+/// The bots to run in one process, one `[[bot]]` table each.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    pub bot: Vec<BotEntry>,
+}
+
+/// One bot of a manifest. Anything not set comes from the command line and
+/// config file.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BotEntry {
+    pub name: Option<String>,
+    pub brain: Option<String>,
+    pub player: Option<bool>,
+    pub player_class: Option<i32>,
+}
+
+fn load<T: DeserializeOwned>(path: &Path, kind: &str) -> Result<T, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {} {}: {}", kind, path.display(), e))?;
+    toml::from_str(&contents)
+        .map_err(|e| format!("Invalid {} {}: {}", kind, path.display(), e).into())
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        load(path, "manifest")
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        load(path, "config file")
+    }
+
+    /// The client settings, with the defaults for anything not set.
+    pub fn client_config(&self) -> ClientConfigBuilder {
+        let mut builder = ClientConfig::builder();
+        if let Some(name) = &self.name {
+            builder = builder.player_name(name);
+        }
+        if let Some(game_description) = &self.game_description {
+            builder = builder.game_description(game_description);
+        }
+        if let Some(connection_timeout) = self.connection_timeout {
+            builder = builder.connection_timeout(Duration::from_secs(connection_timeout));
+        }
+        if let Some(max_retries) = self.max_retries {
+            builder = builder.max_retries(max_retries);
+        }
+        builder
+    }
+
+    /// What to tell servers about our game, with the defaults for anything
+    /// not set.
+    pub fn connect_data(&self, wad_sha1sum: [u8; 20]) -> ConnectDataBuilder {
+        let mut builder = ConnectData::builder(wad_sha1sum);
+        if let Some(lowres_turn) = self.lowres_turn {
+            builder = builder.lowres_turn(lowres_turn);
+        }
+        if let Some(max_players) = self.max_players {
+            builder = builder.max_players(max_players);
+        }
+        if let Some(player_class) = self.player_class {
+            builder = builder.player_class(player_class);
+        }
+        builder
+    }
+
+    /// What to start games with when controlling the lobby, with the
+    /// defaults for anything not set.
+    pub fn game_settings(&self) -> Result<GameSettingsBuilder, String> {
+        let mut builder = GameSettings::builder();
+        if let Some(episode) = self.episode {
+            builder = builder.episode(episode);
+        }
+        if let Some(map) = self.map {
+            builder = builder.map(map);
+        }
+        if let Some(skill) = self.skill {
+            builder = builder.skill(match skill {
+                1 => Skill::Baby,
+                2 => Skill::Easy,
+                3 => Skill::Medium,
+                4 => Skill::Hard,
+                5 => Skill::Nightmare,
+                _ => return Err(format!("Invalid skill {}, expected 1 to 5", skill)),
+            });
+        }
+        if let Some(deathmatch) = self.deathmatch {
+            if !(0..=3).contains(&deathmatch) {
+                return Err(format!(
+                    "Invalid deathmatch {}, expected 0 to 3",
+                    deathmatch
+                ));
+            }
+            builder = builder.deathmatch(deathmatch);
+        }
+        if let Some(timelimit) = self.timelimit {
+            builder = builder.timelimit(timelimit);
+        }
+        if let Some(ticdup) = self.ticdup {
+            if ticdup <= 0 {
+                return Err(format!("Invalid ticdup {}, expected 1 or more", ticdup));
+            }
+            builder = builder.ticdup(ticdup);
+        }
+        if let Some(extratics) = self.extratics {
+            if extratics < 0 {
+                return Err(format!(
+                    "Invalid extratics {}, expected 0 or more",
+                    extratics
+                ));
+            }
+            builder = builder.extratics(extratics);
+        }
+        if let Some(nomonsters) = self.nomonsters {
+            builder = builder.nomonsters(nomonsters);
+        }
+        if let Some(fast) = self.fast {
+            builder = builder.fast_monsters(fast);
+        }
+        if let Some(respawn) = self.respawn {
+            builder = builder.respawn_monsters(respawn);
+        }
+        if let Some(lowres_turn) = self.lowres_turn {
+            builder = builder.lowres_turn(lowres_turn);
+        }
+        Ok(builder)
+    }
+
+    /// Fills in everything not set here from `other`.
+    pub fn or(self, other: Config) -> Config {
+        Config {
+            name: self.name.or(other.name),
+            player_class: self.player_class.or(other.player_class),
+            max_players: self.max_players.or(other.max_players),
+            lowres_turn: self.lowres_turn.or(other.lowres_turn),
+            game_description: self.game_description.or(other.game_description),
+            connection_timeout: self.connection_timeout.or(other.connection_timeout),
+            max_retries: self.max_retries.or(other.max_retries),
+            join_retries: self.join_retries.or(other.join_retries),
+            launch_players: self.launch_players.or(other.launch_players),
+            episode: self.episode.or(other.episode),
+            map: self.map.or(other.map),
+            skill: self.skill.or(other.skill),
+            deathmatch: self.deathmatch.or(other.deathmatch),
+            timelimit: self.timelimit.or(other.timelimit),
+            ticdup: self.ticdup.or(other.ticdup),
+            extratics: self.extratics.or(other.extratics),
+            nomonsters: self.nomonsters.or(other.nomonsters),
+            fast: self.fast.or(other.fast),
+            respawn: self.respawn.or(other.respawn),
+        }
+    }
+}
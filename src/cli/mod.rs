@@ -0,0 +1,4 @@
+//! The parts of the `hydra-bot` binary that are not part of the library:
+//! nothing here is reachable from `hydra_bot`.
+
+pub mod config;
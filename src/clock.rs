@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Source of time for the game loop and the network client.
+///
+/// Everything that schedules work (resends, keepalives, tic generation,
+/// stall detection) goes through this instead of calling `Instant::now()`
+/// directly, so it can be driven by a virtual clock.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> Instant;
+
+    /// Waits for `duration` to pass on this clock.
+    fn sleep(&self, duration: Duration);
+
+    /// How long it has been since `since`.
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.now().saturating_duration_since(since)
+    }
+}
+
+/// The real, monotonic clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to, for driving timing logic
+/// deterministically. Sleeping advances it instead of blocking.
+#[derive(Debug)]
+pub struct VirtualClock {
+    origin: Instant,
+    offset: Mutex<Duration>,
+}
+
+impl VirtualClock {
+    /// A clock starting at the current time, which then stands still.
+    pub fn new() -> Self {
+        VirtualClock {
+            origin: Instant::now(),
+            offset: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the time forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock().unwrap() += duration;
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.origin + *self.offset.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+
+use crate::action::Weapon;
+use crate::net::{TicCmd, BT_CHANGE, BT_SPECIAL, BT_WEAPONMASK, BT_WEAPONSHIFT};
+
+// The order `change weapon` goes through the slots in when no weapon is named.
+const WEAPON_CYCLE: [Weapon; 7] = [
+    Weapon::Fist,
+    Weapon::Pistol,
+    Weapon::Shotgun,
+    Weapon::Chaingun,
+    Weapon::RocketLauncher,
+    Weapon::PlasmaRifle,
+    Weapon::Bfg,
+];
+
+/// Orders players can give a bot over chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotCommand {
+    /// Follows the named player. Only brains that implement it can.
+    Follow(String),
+    /// Stops moving until told to carry on or do something else.
+    Hold,
+    /// Goes back to moving after holding.
+    CarryOn,
+    /// Heads for the exit of the level. Only brains that implement it can.
+    GoToExit,
+    /// Switches to the given weapon, or the next one.
+    ChangeWeapon(Option<Weapon>),
+    /// Says what the bot is up to.
+    Status,
+}
+
+impl BotCommand {
+    /// Reads an order like `follow alice` or `change weapon shotgun`. Chat
+    /// only has capitals, so case does not matter.
+    pub fn parse(order: &str) -> Option<BotCommand> {
+        let order = order.trim().to_ascii_uppercase();
+        let words: Vec<_> = order.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["FOLLOW", name @ ..] if !name.is_empty() => Some(BotCommand::Follow(name.join(" "))),
+            ["HOLD"] | ["HOLD", "HERE" | "POSITION"] | ["STAY"] => Some(BotCommand::Hold),
+            ["CARRY", "ON"] | ["RESUME"] | ["GO"] => Some(BotCommand::CarryOn),
+            ["GO", "TO", "EXIT"] | ["GO", "TO", "THE", "EXIT"] | ["EXIT"] => {
+                Some(BotCommand::GoToExit)
+            }
+            ["CHANGE", "WEAPON", weapon @ ..] | ["WEAPON", weapon @ ..] => match weapon {
+                [] => Some(BotCommand::ChangeWeapon(None)),
+                weapon => {
+                    parse_weapon(&weapon.join(" ")).map(|w| BotCommand::ChangeWeapon(Some(w)))
+                }
+            },
+            ["STATUS"] | ["REPORT"] => Some(BotCommand::Status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BotCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotCommand::Follow(name) => write!(f, "follow {}", name),
+            BotCommand::Hold => write!(f, "hold here"),
+            BotCommand::CarryOn => write!(f, "carry on"),
+            BotCommand::GoToExit => write!(f, "go to exit"),
+            BotCommand::ChangeWeapon(Some(weapon)) => {
+                write!(f, "change weapon {}", weapon_name(*weapon))
+            }
+            BotCommand::ChangeWeapon(None) => write!(f, "change weapon"),
+            BotCommand::Status => write!(f, "status"),
+        }
+    }
+}
+
+/// Returns the order in a chat line meant for the bot called `name`: one
+/// that starts with the name, like `HYDRABOT: HOLD`, or any line sent to the
+/// bot alone.
+pub fn addressed_to<'a>(text: &'a str, name: &str, private: bool) -> Option<&'a str> {
+    let rest = text
+        .get(..name.len())
+        .filter(|start| start.eq_ignore_ascii_case(name))
+        .map(|_| &text[name.len()..]);
+
+    match rest {
+        Some(rest) if rest.is_empty() || rest.starts_with([':', ',', ' ']) => {
+            Some(rest.trim_start_matches([':', ',']).trim())
+        }
+        _ if private => Some(text.trim()),
+        _ => None,
+    }
+}
+
+fn parse_weapon(name: &str) -> Option<Weapon> {
+    match name {
+        "FIST" => Some(Weapon::Fist),
+        "PISTOL" => Some(Weapon::Pistol),
+        "SHOTGUN" => Some(Weapon::Shotgun),
+        "SUPER SHOTGUN" | "SSG" => Some(Weapon::SuperShotgun),
+        "CHAINGUN" => Some(Weapon::Chaingun),
+        "ROCKET LAUNCHER" | "ROCKETS" | "ROCKET" => Some(Weapon::RocketLauncher),
+        "PLASMA RIFLE" | "PLASMA" => Some(Weapon::PlasmaRifle),
+        "BFG" => Some(Weapon::Bfg),
+        "CHAINSAW" => Some(Weapon::Chainsaw),
+        _ => None,
+    }
+}
+
+fn weapon_name(weapon: Weapon) -> &'static str {
+    match weapon {
+        Weapon::Fist => "fist",
+        Weapon::Pistol => "pistol",
+        Weapon::Shotgun => "shotgun",
+        Weapon::SuperShotgun => "super shotgun",
+        Weapon::Chaingun => "chaingun",
+        Weapon::RocketLauncher => "rocket launcher",
+        Weapon::PlasmaRifle => "plasma rifle",
+        Weapon::Bfg => "BFG",
+        Weapon::Chainsaw => "chainsaw",
+    }
+}
+
+/// The orders any brain follows, whatever it decides: they are applied on
+/// top of its commands.
+#[derive(Debug, Default)]
+pub(crate) struct Orders {
+    holding: bool,
+    weapon_change: Option<Weapon>,
+    last_weapon: Option<Weapon>,
+}
+
+impl Orders {
+    /// Takes an order the brain did not handle itself, returning the reply.
+    pub fn obey(&mut self, command: &BotCommand) -> String {
+        match command {
+            BotCommand::Hold => {
+                self.holding = true;
+                "Holding here".to_string()
+            }
+            BotCommand::CarryOn => {
+                self.holding = false;
+                "Carrying on".to_string()
+            }
+            BotCommand::ChangeWeapon(weapon) => {
+                let weapon = weapon.unwrap_or_else(|| self.next_weapon());
+                self.weapon_change = Some(weapon);
+                self.last_weapon = Some(weapon);
+                format!("Switching to the {}", weapon_name(weapon))
+            }
+            BotCommand::Status => match self.holding {
+                true => "Holding position".to_string(),
+                false => "On the move".to_string(),
+            },
+            BotCommand::Follow(_) | BotCommand::GoToExit => format!("Can't {}", command),
+        }
+    }
+
+    /// Lets the brain move again, as it took a new order.
+    pub fn release(&mut self) {
+        self.holding = false;
+    }
+
+    pub fn apply(&mut self, cmd: &mut TicCmd) {
+        if self.holding {
+            cmd.forwardmove = 0;
+            cmd.sidemove = 0;
+        }
+
+        // Specials use the weapon bits for something else, so the change
+        // waits for a tic without one.
+        if cmd.buttons & BT_SPECIAL != 0 {
+            return;
+        }
+        if let Some(weapon) = self.weapon_change.take() {
+            cmd.buttons &= !BT_WEAPONMASK;
+            cmd.buttons |= BT_CHANGE | ((weapon.slot() << BT_WEAPONSHIFT) & BT_WEAPONMASK);
+        }
+    }
+
+    fn next_weapon(&self) -> Weapon {
+        let current = self
+            .last_weapon
+            .and_then(|weapon| WEAPON_CYCLE.iter().position(|&w| w == weapon));
+        match current {
+            Some(i) => WEAPON_CYCLE[(i + 1) % WEAPON_CYCLE.len()],
+            // Everyone starts with the pistol.
+            None => Weapon::Shotgun,
+        }
+    }
+}
@@ -1,11 +1,19 @@
-use crate::net::client::Client;
-use crate::net::{TicCmd, BACKUPTICS, NET_MAXPLAYERS};
-
+use crate::brain::{Brain, Observation};
+use crate::chat::{ChatDestination, ChatMessage};
+use crate::clock::Clock;
+use crate::command::{self, BotCommand, Orders};
+use crate::net::client::{Client, MAX_QUEUED_EVENTS};
+use crate::net::{
+    ConsoleMessage, GameMode, GameSettings, ReceivedTic, TicCmd, BACKUPTICS, BT_SPECIAL,
+    NET_MAXPLAYERS,
+};
+
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tracing::{debug, warn};
+use tracing::{debug, info};
 
+/// Tics the game runs per second.
 pub const TICRATE: u32 = 35;
-const MAX_NETGAME_STALL_TICS: u32 = 2;
 
 #[derive(Clone, Copy)]
 struct TiccmdSet {
@@ -13,8 +21,20 @@ struct TiccmdSet {
     ingame: [bool; NET_MAXPLAYERS],
 }
 
+/// The game loop, following Chocolate Doom's `d_loop.c`.
+///
+/// It builds the local player's commands at the tic rate, runs the tics
+/// once every player's commands for them arrived, and keeps the pace in
+/// step with the server.
 pub struct Game {
+    clock: Arc<dyn Clock>,
+    brain: Box<dyn Brain>,
+    orders: Orders,
+    console: Vec<ConsoleMessage>,
+    settings: GameSettings,
+    start_time: Instant,
     ticdata: [TiccmdSet; BACKUPTICS],
+    last_run: TiccmdSet,
     maketic: i32,
     recvtic: i32,
     gametic: i32,
@@ -34,20 +54,41 @@ pub struct Game {
 }
 
 impl Game {
-    pub fn new() -> Self {
+    /// Creates the game loop state for the settings the server sent when the
+    /// game started, so that `-dup`, `-oldsync` and our player slot are
+    /// honored. The brain is asked for the local player's command for every
+    /// tic the loop builds.
+    pub fn new(settings: &GameSettings, clock: Arc<dyn Clock>, brain: Box<dyn Brain>) -> Self {
+        // Drones are not assigned a player slot, and view player 0.
+        let localplayer = settings.consoleplayer.max(0);
+        let mut local_playeringame = [false; NET_MAXPLAYERS];
+        for (i, ingame) in local_playeringame.iter_mut().enumerate() {
+            *ingame = (i as i32) < settings.num_players;
+        }
+
+        let now = clock.now();
+        let empty_set = TiccmdSet {
+            cmds: [TicCmd::default(); NET_MAXPLAYERS],
+            ingame: [false; NET_MAXPLAYERS],
+        };
+
         Game {
-            ticdata: [TiccmdSet {
-                cmds: [TicCmd::default(); NET_MAXPLAYERS],
-                ingame: [false; NET_MAXPLAYERS],
-            }; BACKUPTICS],
+            clock,
+            brain,
+            orders: Orders::default(),
+            console: Vec::new(),
+            settings: *settings,
+            start_time: now,
+            ticdata: [empty_set; BACKUPTICS],
+            last_run: empty_set,
             maketic: 0,
             recvtic: 0,
             gametic: 0,
-            localplayer: 0,
+            localplayer,
             offsetms: 0,
-            ticdup: 1,
-            new_sync: true,
-            local_playeringame: [false; NET_MAXPLAYERS],
+            ticdup: settings.ticdup.max(1),
+            new_sync: settings.new_sync != 0,
+            local_playeringame,
             frameskip: [false; 4],
             singletics: false,
             lasttime: 0,
@@ -55,25 +96,27 @@ impl Game {
             frameon: 0,
             oldnettics: 0,
             oldentertics: 0,
-            last_net_update: Instant::now(),
+            last_net_update: now,
         }
     }
 
     fn get_adjusted_time(&self) -> u32 {
-        let time_ms = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i32;
+        let mut time_ms = self.clock.elapsed(self.start_time).as_millis() as i64;
 
         if self.new_sync {
-            (time_ms + self.offsetms) as u32 * TICRATE / 1000
-        } else {
-            time_ms as u32 * TICRATE / 1000
+            time_ms += self.offsetms as i64;
         }
+
+        (time_ms.max(0) * TICRATE as i64 / 1000) as u32
     }
 
     fn build_new_tic(&mut self, client: &mut Client) -> bool {
-        let gameticdiv = self.maketic / self.ticdup;
+        // Drones only watch, they do not get a say in the game.
+        if client.is_drone() {
+            return false;
+        }
+
+        let gameticdiv = self.gametic / self.ticdup;
 
         if self.new_sync {
             if !client.is_connected() && self.maketic - gameticdiv > 2 {
@@ -87,8 +130,19 @@ impl Game {
             return false;
         }
 
-        let mut cmd = TicCmd::default();
-        client.build_ticcmd(&mut cmd, self.maketic as u32);
+        let mut cmd = self.brain.think(&Observation {
+            settings: &self.settings,
+            maketic: self.maketic as u32,
+            gametic: self.gametic as u32,
+            cmds: &self.last_run.cmds,
+            ingame: &self.last_run.ingame,
+            console: &self.console,
+        });
+        self.console.clear();
+        if cmd.chatchar == 0 {
+            cmd.chatchar = client.next_chat_char().unwrap_or(0);
+        }
+        self.orders.apply(&mut cmd);
 
         if client.is_connected() {
             client.send_ticcmd(&cmd, self.maketic as u32);
@@ -103,21 +157,35 @@ impl Game {
         true
     }
 
+    /// Takes in the tics the client received and builds the commands that
+    /// are due.
     pub fn net_update(&mut self, client: &mut Client) {
         if self.singletics {
             return;
         }
 
-        let now = Instant::now();
-        if now.duration_since(self.last_net_update) < Duration::from_millis(1000 / TICRATE as u64) {
+        let now = self.clock.now();
+        if now.saturating_duration_since(self.last_net_update)
+            < Duration::from_millis(1000 / TICRATE as u64)
+        {
             return;
         }
         self.last_net_update = now;
 
-        let nowtime = self.get_adjusted_time();
-        self.lasttime = nowtime as i32;
-
         client.run();
+        self.offsetms = client.get_offset_ms();
+        // Only players' brains get to think, and so to read the console.
+        if !client.is_drone() {
+            self.console.extend(client.take_console_messages());
+            // Brains are not asked while the game waits on the server, so
+            // only the latest messages are kept for when they are.
+            let excess = self.console.len().saturating_sub(MAX_QUEUED_EVENTS);
+            self.console.drain(..excess);
+        }
+
+        while let Some(tic) = client.next_received_tic() {
+            self.receive_tic(client, &tic);
+        }
 
         let nowtime = (self.get_adjusted_time() / self.ticdup as u32) as i32;
         let mut newtics = nowtime.saturating_sub(self.lasttime) as u32;
@@ -139,58 +207,45 @@ impl Game {
         }
     }
 
+    /// Starts counting time from now, right before the first tick.
     pub fn start_loop(&mut self) {
         self.lasttime = (self.get_adjusted_time() / self.ticdup as u32) as i32;
     }
 
+    /// Runs one iteration of the loop, meant to be called once per tic and
+    /// whenever the server sends something. Commands are built as time
+    /// passes, and tics only run once the server has sent them.
     pub fn tick(&mut self, client: &mut Client) {
         let enter_tic = (self.get_adjusted_time() / self.ticdup as u32) as i32;
 
-        let mut counts;
-        let mut lowtic;
-
         if self.singletics {
             self.build_new_tic(client);
         } else {
             self.net_update(client);
         }
 
-        lowtic = self.get_low_tic();
+        let lowtic = self.get_low_tic(client);
 
         let availabletics = lowtic - self.gametic / self.ticdup;
 
         let realtics = enter_tic - self.oldentertics;
         self.oldentertics = enter_tic;
 
-        if self.new_sync {
-            counts = availabletics;
+        let mut counts = if self.new_sync {
+            availabletics
         } else {
-            counts = realtics.min(availabletics).max(1);
-
             if client.is_connected() {
                 self.old_net_sync();
             }
-        }
-
-        while !self.players_in_game(client) || lowtic < self.gametic / self.ticdup + counts {
-            self.net_update(client);
-
-            lowtic = self.get_low_tic();
-
-            if lowtic < self.gametic / self.ticdup {
-                panic!("TryRunTics: lowtic < gametic");
-            }
 
-            if lowtic < self.gametic / self.ticdup + counts {
-                if self.get_adjusted_time() / self.ticdup as u32 - enter_tic as u32
-                    >= MAX_NETGAME_STALL_TICS
-                {
-                    warn!("work stall detected");
-                    return;
-                }
+            realtics.min(availabletics).max(1)
+        };
 
-                std::thread::sleep(std::time::Duration::from_millis(1));
-            }
+        // Rather than waiting here for the missing tics, return and get called
+        // again on the next tic, or when the server sends something if the
+        // caller watches the socket like AsyncClient::play does.
+        if !self.players_in_game(client) || lowtic < self.gametic / self.ticdup + counts {
+            return;
         }
 
         while counts > 0 {
@@ -199,6 +254,7 @@ impl Game {
             }
 
             let set = &mut self.ticdata[(self.gametic / self.ticdup) as usize % BACKUPTICS];
+            let mut chat = Vec::new();
 
             if !client.is_connected() {
                 Self::single_player_clear(self.localplayer as usize, set);
@@ -211,20 +267,106 @@ impl Game {
 
                 self.local_playeringame = set.ingame;
 
-                client.run_tic(&set.cmds, &set.ingame);
+                chat.extend(client.run_tic(&set.cmds, &set.ingame));
+                self.last_run = *set;
                 self.gametic += 1;
 
                 Self::ticdup_squash(set);
             }
 
+            for message in &chat {
+                self.handle_chat(client, message);
+            }
+
             self.net_update(client);
             counts -= 1;
         }
         debug!("Finished running tics. New gametic: {}", self.gametic);
     }
 
-    fn get_low_tic(&self) -> i32 {
-        self.maketic.min(self.recvtic)
+    fn receive_tic(&mut self, client: &Client, tic: &ReceivedTic) {
+        let set = &mut self.ticdata[self.recvtic as usize % BACKUPTICS];
+
+        for i in 0..NET_MAXPLAYERS {
+            // Our own command is the one we built, not the server's echo.
+            if !client.is_drone() && i == self.localplayer as usize {
+                continue;
+            }
+
+            set.cmds[i] = tic.cmds[i];
+            set.ingame[i] = tic.ingame[i];
+        }
+
+        self.recvtic += 1;
+    }
+
+    /// Carries out the orders players give us over chat, and answers them.
+    fn handle_chat(&mut self, client: &mut Client, message: &ChatMessage) {
+        // Drones have no commands to follow orders or answer with, and
+        // orders sent to someone else never show up on our screen.
+        let localplayer = self.localplayer as usize;
+        if client.is_drone() || message.from == localplayer || !message.is_for(Some(localplayer)) {
+            return;
+        }
+
+        let private = message.to == ChatDestination::Player(localplayer);
+        let Some(order) = command::addressed_to(&message.text, client.get_name(), private) else {
+            return;
+        };
+
+        let reply = match BotCommand::parse(order) {
+            Some(mut command) => {
+                if let BotCommand::Follow(name) = &mut command {
+                    *name = match name.as_str() {
+                        "ME" => message.name.clone(),
+                        // Chat only has capitals, so the name is looked up
+                        // in the lobby to give brains the real one.
+                        _ => client
+                            .get_lobby()
+                            .and_then(|lobby| {
+                                lobby
+                                    .players
+                                    .into_iter()
+                                    .find(|player| player.name.eq_ignore_ascii_case(name))
+                            })
+                            .map_or_else(|| name.clone(), |player| player.name),
+                    };
+                }
+                info!("{} ordered us to {}", message.name, command);
+
+                match self.brain.command(&command) {
+                    Some(reply) => {
+                        if matches!(
+                            command,
+                            BotCommand::Follow(_) | BotCommand::GoToExit | BotCommand::CarryOn
+                        ) {
+                            self.orders.release();
+                        }
+                        reply
+                    }
+                    None if command == BotCommand::Status => {
+                        // Only Doom II and Final Doom have no episodes.
+                        let map = match client.get_gamemode() == GameMode::Commercial {
+                            true => format!("Map {}", self.settings.map),
+                            false => format!("E{}M{}", self.settings.episode, self.settings.map),
+                        };
+                        format!("{}: {}", map, self.orders.obey(&command))
+                    }
+                    None => self.orders.obey(&command),
+                }
+            }
+            None => format!("Unknown order: {}", order),
+        };
+
+        client.send_chat(ChatDestination::Player(message.from), &reply);
+    }
+
+    fn get_low_tic(&self, client: &Client) -> i32 {
+        if client.is_connected() && (client.is_drone() || self.recvtic < self.maketic) {
+            self.recvtic
+        } else {
+            self.maketic
+        }
     }
 
     fn old_net_sync(&mut self) {
@@ -266,8 +408,7 @@ impl Game {
     fn ticdup_squash(set: &mut TiccmdSet) {
         for cmd in &mut set.cmds {
             cmd.chatchar = 0;
-            if cmd.buttons & 0x80 != 0 {
-                // 0x80 is the value for BT_SPECIAL
+            if cmd.buttons & BT_SPECIAL != 0 {
                 cmd.buttons = 0;
             }
         }
@@ -0,0 +1,84 @@
+//! A client for the Chocolate Doom v3 network protocol, built to run bots.
+//!
+//! A bot is made of three parts:
+//!
+//! - a [`Client`] that joins a server and keeps the connection going,
+//! - a [`Game`] that runs the game loop in step with the server,
+//! - a [`Brain`](brain::Brain) deciding what the player does on every tic.
+//!
+//! [`AsyncClient`] drives the same client from a tokio runtime, for running
+//! many bots without a thread each.
+//!
+//! The blocking client has to be paced by the caller, which a
+//! [`TicScheduler`](scheduler::TicScheduler) does on the game's tic grid.
+//! Tics the server sends late then wait for the next one to run.
+//! [`AsyncClient::play`] does all of this on its own, and also wakes up to
+//! run them as soon as they arrive.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::thread;
+//! use std::time::Duration;
+//!
+//! use hydra_bot::clock::{Clock, MonotonicClock};
+//! use hydra_bot::game::TICRATE;
+//! use hydra_bot::net::{resolve_address, ConnectData};
+//! use hydra_bot::scheduler::TicScheduler;
+//! use hydra_bot::{brain, Client, ClientConfig, Game};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let clock: Arc<dyn Clock> = Arc::new(MonotonicClock);
+//! let config = ClientConfig::builder().player_name("Marine").build();
+//! let mut client = Client::new(config, clock.clone())?;
+//! client.init();
+//!
+//! let wad_sha1sum = [0; 20];
+//! let connect_data = ConnectData::builder(wad_sha1sum).drone(false).build();
+//! let addr = resolve_address("localhost", client.get_local_addr())?;
+//! client.connect(addr, connect_data)?;
+//!
+//! let settings = loop {
+//!     client.run();
+//!     if let Some(settings) = client.get_settings() {
+//!         break settings;
+//!     }
+//!     thread::sleep(Duration::from_millis(10));
+//! };
+//!
+//! let brain = brain::from_name("wander").unwrap();
+//! let mut game = Game::new(&settings, clock.clone(), brain);
+//! game.start_loop();
+//! let mut scheduler = TicScheduler::new(clock, TICRATE);
+//! while client.is_connected() {
+//!     thread::sleep(scheduler.until_next_tic());
+//!     client.run();
+//!     if scheduler.poll() {
+//!         game.tick(&mut client);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// Intents and the [`ActionCompiler`](action::ActionCompiler) that turns them into ticcmds.
+pub mod action;
+/// What the player does on every tic.
+pub mod brain;
+/// Chat typed one `chatchar` at a time.
+pub mod chat;
+/// Time sources for the game loop and the client.
+pub mod clock;
+/// Orders given to bots in chat.
+pub mod command;
+/// The game loop.
+pub mod game;
+/// The network protocol and the clients speaking it.
+pub mod net;
+/// Pacing for the game loop.
+pub mod scheduler;
+
+pub use self::game::Game;
+pub use self::net::async_client::AsyncClient;
+pub use self::net::client::{Client, ClientConfig, ClientConfigBuilder};
+pub use self::net::packet::Packet;
+pub use self::net::ClientError;
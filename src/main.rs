@@ -1,90 +1,431 @@
-use std::fs::File;
-use std::io::Read;
-use std::net::SocketAddr;
-use std::path::PathBuf;
-use std::thread;
-use std::time::Duration;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{future, process};
 
 use argh::FromArgs;
 use sha1::{Digest, Sha1};
-use tracing::{error, info};
+use tokio::sync::watch;
+use tokio::{signal, time};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-mod game;
-mod net;
+use hydra_bot::brain::{self, Brain};
+use hydra_bot::clock::MonotonicClock;
+use hydra_bot::net::{
+    bind_socket, resolve_address, resolve_address_with_port, ClientEvent, ConnectData,
+    ConsoleMessage, GameSettings, ServerFilter, ServerInfo, DEFAULT_MASTER_PORT,
+    DEFAULT_MASTER_SERVER, DEFAULT_PORT,
+};
+use hydra_bot::{AsyncClient, Client, ClientConfig};
 
-use self::game::Game;
-use self::net::client::Client;
-use self::net::{ConnectData, GameMission, GameMode};
+mod cli;
+
+use self::cli::config::{BotEntry, Config, Manifest};
+
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+const WAIT_DATA_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_JOIN_RETRIES: u32 = 2;
+const JOIN_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(FromArgs)]
 /// An AI player implementation compatible with Chocolate Doom v3.
 struct Args {
-    /// which server to connect to
+    /// which server to connect to, as host[:port] (default port: 2342)
     #[argh(option, short = 'a')]
-    address: String,
+    address: Option<String>,
+
+    /// local address to send from, as ip[:port] (defaults to every interface)
+    #[argh(option, from_str_fn(parse_bind_address))]
+    bind: Option<SocketAddr>,
 
     /// the WAD path to load
     #[argh(option, short = 'i')]
     iwad: PathBuf,
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+    /// search the local network for servers
+    #[argh(switch)]
+    local_search: bool,
 
-    let args: Args = argh::from_env();
+    /// where to send the search query (defaults to the LAN broadcast address)
+    #[argh(option)]
+    search_address: Option<String>,
 
-    info!("Initializing client");
-    let mut client = Client::new("HydraBot".to_string(), true)?;
-    client.init();
+    /// search the master server for internet servers
+    #[argh(switch)]
+    search: bool,
 
-    info!("Initializing game");
-    let mut game = Game::new();
+    /// master server used for searching and NAT hole punching, on port
+    /// 27020 unless given (defaults to the Chocolate Doom one when searching)
+    #[argh(option)]
+    master: Option<String>,
 
-    info!("Connecting to server");
-    let server_addr = args.address.parse::<SocketAddr>()?;
+    /// join the best server found by the search
+    #[argh(switch)]
+    auto_join: bool,
 
-    let mut wad_file = File::open(&args.iwad)?;
-    let mut wad_contents = Vec::new();
-    wad_file.read_to_end(&mut wad_contents)?;
-    let wad_sha1 = Sha1::digest(&wad_contents);
+    /// how many free player slots a server needs to be joined (default: 1)
+    #[argh(option, default = "1")]
+    free_slots: i32,
 
-    info!(
-        sha1 = hex::encode(&wad_sha1),
-        "Calculated SHA1 for the WAD file"
+    /// only stay on servers running the same WAD
+    #[argh(switch)]
+    match_wad: bool,
+
+    /// join as a player instead of a spectating drone
+    #[argh(switch)]
+    player: bool,
+
+    /// which bot behavior to use: idle, forward or wander (default: forward)
+    #[argh(option, default = "String::from(\"forward\")")]
+    brain: String,
+
+    /// how many bots to run, all alike but for their names (default: 1)
+    #[argh(option, default = "1")]
+    count: u32,
+
+    /// TOML file listing the bots to run, as [[bot]] tables with a name,
+    /// brain, player and player-class each
+    #[argh(option)]
+    manifest: Option<PathBuf>,
+
+    /// file to append the servers' console messages to, one line each with
+    /// the time and the bot that got it
+    #[argh(option)]
+    console_log: Option<PathBuf>,
+
+    /// TOML file with defaults for the options below, using the same names
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// the name shown to other players (default: HydraBot)
+    #[argh(option)]
+    name: Option<String>,
+
+    /// the player class, for games that have them (default: 0)
+    #[argh(option)]
+    player_class: Option<i32>,
+
+    /// the most players the bot supports in a game (default: 8)
+    #[argh(option)]
+    max_players: Option<i32>,
+
+    /// ask the server for low resolution turning, true or false (default:
+    /// false)
+    #[argh(option)]
+    lowres_turn: Option<bool>,
+
+    /// the version string sent to servers (default: Chocolate Doom 3.0.1)
+    #[argh(option)]
+    game_description: Option<String>,
+
+    /// seconds to wait for a server to let us in (default: 60)
+    #[argh(option)]
+    connection_timeout: Option<u64>,
+
+    /// how many connection requests to send a server before giving up
+    /// (default: 10)
+    #[argh(option)]
+    max_retries: Option<u32>,
+
+    /// how many more times to try joining a server that did not answer
+    /// (default: 2)
+    #[argh(option)]
+    join_retries: Option<u32>,
+
+    /// when controlling the lobby, start the game once the server counts
+    /// this many players in it as ready
+    #[argh(option)]
+    launch_players: Option<usize>,
+
+    /// the episode to start on (default: 1)
+    #[argh(option)]
+    episode: Option<i32>,
+
+    /// the map to start on (default: 1)
+    #[argh(option)]
+    map: Option<i32>,
+
+    /// the skill level, from 1 to 5 (default: 3)
+    #[argh(option)]
+    skill: Option<u8>,
+
+    /// 0 for cooperative, 1 for deathmatch, 2 for altdeath or 3 for
+    /// deathmatch 3.0 (default: 0)
+    #[argh(option)]
+    deathmatch: Option<i32>,
+
+    /// minutes to play each level for, 0 for no limit (default: 0)
+    #[argh(option)]
+    timelimit: Option<u32>,
+
+    /// how many tics to run each command for (default: 1)
+    #[argh(option)]
+    ticdup: Option<i32>,
+
+    /// how many older tics to resend with each new one (default: 0)
+    #[argh(option)]
+    extratics: Option<i32>,
+
+    /// start without monsters, true or false (default: false)
+    #[argh(option)]
+    nomonsters: Option<bool>,
+
+    /// start with fast monsters, true or false (default: false)
+    #[argh(option)]
+    fast: Option<bool>,
+
+    /// start with respawning monsters, true or false (default: false)
+    #[argh(option)]
+    respawn: Option<bool>,
+}
+
+fn parse_bind_address(value: &str) -> Result<SocketAddr, String> {
+    value
+        .parse::<SocketAddr>()
+        .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 0)))
+        .map_err(|_| format!("Invalid bind address {:?}", value))
+}
+
+fn print_servers(servers: &[ServerInfo]) {
+    println!(
+        "{:>6}  {:>7}  {:<21}  Description",
+        "Ping", "Players", "Address"
     );
 
-    let connect_data = ConnectData {
-        gamemode: GameMode::Commercial as i32,
-        gamemission: GameMission::Doom as i32,
-        lowres_turn: 0,
-        drone: 1,
-        max_players: 8,
-        is_freedoom: 0,
-        wad_sha1sum: wad_sha1.into(),
-        deh_sha1sum: [0; 20],
-        player_class: 0,
-    };
+    for server in servers {
+        let players = format!("{}/{}", server.query.num_players, server.query.max_players);
+        println!(
+            "{:>4}ms  {:>7}  {:<21}  {}",
+            server.ping.as_millis(),
+            players,
+            server.addr.to_string(),
+            server.query.description
+        );
+    }
+}
 
-    info!("Connecting with data: {:?}", connect_data);
+fn search(
+    client: &mut Client,
+    args: &Args,
+    master: Option<SocketAddr>,
+) -> Result<Vec<ServerInfo>, Box<dyn std::error::Error>> {
+    if let Some(master) = master.filter(|_| args.search) {
+        let addrs = client.query_master(master, SEARCH_TIMEOUT)?;
+        Ok(client.query_servers(&addrs, SEARCH_TIMEOUT))
+    } else {
+        let target = match &args.search_address {
+            Some(address) => resolve_address(address, client.get_local_addr())?,
+            None => SocketAddr::from(([255, 255, 255, 255], DEFAULT_PORT)),
+        };
+        Ok(client.search_lan(target, SEARCH_TIMEOUT)?)
+    }
+}
+
+impl Args {
+    /// The settings given on the command line, falling back to the config
+    /// file.
+    fn config(&self) -> Result<Config, Box<dyn std::error::Error>> {
+        let cli = Config {
+            name: self.name.clone(),
+            player_class: self.player_class,
+            max_players: self.max_players,
+            lowres_turn: self.lowres_turn,
+            game_description: self.game_description.clone(),
+            connection_timeout: self.connection_timeout,
+            max_retries: self.max_retries,
+            join_retries: self.join_retries,
+            launch_players: self.launch_players,
+            episode: self.episode,
+            map: self.map,
+            skill: self.skill,
+            deathmatch: self.deathmatch,
+            timelimit: self.timelimit,
+            ticdup: self.ticdup,
+            extratics: self.extratics,
+            nomonsters: self.nomonsters,
+            fast: self.fast,
+            respawn: self.respawn,
+        };
+
+        match &self.config {
+            Some(path) => Ok(cli.or(Config::load(path)?)),
+            None => Ok(cli),
+        }
+    }
+}
+
+fn make_brain(name: &str) -> Result<Box<dyn Brain>, Box<dyn Error>> {
+    brain::from_name(name).ok_or_else(|| {
+        format!(
+            "Unknown brain {:?}, expected one of: {}",
+            name,
+            brain::BRAIN_NAMES.join(", ")
+        )
+        .into()
+    })
+}
+
+/// Everything that differs between the bots of one process.
+struct Bot {
+    client_config: ClientConfig,
+    connect_data: ConnectData,
+    /// The servers this bot may join, going by its own game and WAD.
+    filter: ServerFilter,
+    brain: Box<dyn Brain>,
+}
+
+impl Args {
+    /// The bots to run: the ones listed in the manifest, or `--count` of the
+    /// one described by the options. When there are several, the default
+    /// names are numbered, and each takes the next port after `--bind`.
+    fn bots(&self, config: &Config, wad_sha1sum: [u8; 20]) -> Result<Vec<Bot>, Box<dyn Error>> {
+        let entries = match &self.manifest {
+            Some(path) => Manifest::load(path)?.bot,
+            None => vec![BotEntry::default(); self.count as usize],
+        };
+        if entries.is_empty() {
+            return Err("No bots to run".into());
+        }
+
+        let base_name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| ClientConfig::default().player_name);
+        let numbered = entries.len() > 1;
+
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let name = entry.name.unwrap_or_else(|| match numbered {
+                    true => format!("{}{}", base_name, i + 1),
+                    false => base_name.clone(),
+                });
+                let drone = !entry.player.unwrap_or(self.player);
+
+                let mut client_config = config.client_config().player_name(name).drone(drone);
+                if let Some(bind) = self.bind {
+                    let port = match bind.port() {
+                        0 => 0,
+                        port => port
+                            .checked_add(i as u16)
+                            .ok_or("Not enough ports after --bind for every bot")?,
+                    };
+                    client_config = client_config.bind_addr(SocketAddr::new(bind.ip(), port));
+                }
+
+                let mut connect_data = config.connect_data(wad_sha1sum).drone(drone);
+                if let Some(player_class) = entry.player_class {
+                    connect_data = connect_data.player_class(player_class);
+                }
+
+                let connect_data = connect_data.build();
+                let filter = ServerFilter {
+                    gamemode: Some(connect_data.gamemode),
+                    gamemission: Some(connect_data.gamemission),
+                    free_slots: self.free_slots,
+                    wad_sha1sum: self.match_wad.then_some(connect_data.wad_sha1sum),
+                    ..Default::default()
+                };
+
+                Ok(Bot {
+                    client_config: client_config.build(),
+                    connect_data,
+                    filter,
+                    brain: make_brain(entry.brain.as_deref().unwrap_or(&self.brain))?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// When to start the game, for the bot that controls the lobby.
+#[derive(Clone, Copy)]
+struct Launch {
+    players: usize,
+    settings: GameSettings,
+}
+
+/// Where the servers' console messages are kept, for reading after the fact.
+struct ConsoleLog {
+    file: Mutex<File>,
+}
+
+impl ConsoleLog {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(ConsoleLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes `<seconds since the epoch> <bot>: <text>`, with the message
+    /// on a single line.
+    fn write(&self, bot: &str, message: &ConsoleMessage) {
+        let time = message.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let text = message.text.trim_end().replace('\n', " ");
+        let line = format!(
+            "{}.{:03} {}: {}\n",
+            time.as_secs(),
+            time.subsec_millis(),
+            bot,
+            text
+        );
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Failed to write to the console log: {}", e);
+        }
+    }
+}
+
+/// A server the bots may try to join.
+struct Candidate {
+    addr: SocketAddr,
+    /// What the server told about itself, if it was found by searching.
+    info: Option<ServerInfo>,
+}
+
+/// What every bot of the process shares.
+struct Session {
+    candidates: Arc<[Candidate]>,
+    master: Option<SocketAddr>,
+    join_retries: u32,
+    launch: Option<Launch>,
+    console_log: Option<ConsoleLog>,
+    /// Becomes true when the bots should leave their servers.
+    shutdown: watch::Receiver<bool>,
+}
+
+async fn join(
+    client: &mut AsyncClient,
+    server_addr: SocketAddr,
+    connect_data: ConnectData,
+    filter: &ServerFilter,
+    join_retries: u32,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!("Connecting to server at {}", server_addr);
 
     let mut retry_count = 0;
-    const MAX_RETRIES: u32 = 3;
+    let max_attempts = join_retries + 1;
 
-    while retry_count < MAX_RETRIES {
-        match client.connect(server_addr, connect_data) {
+    while retry_count < max_attempts {
+        match client.connect(server_addr, connect_data).await {
             Ok(_) => {
                 info!("Connected to server successfully");
                 break;
             }
             Err(e) => {
                 error!("Failed to connect to server: {}", e);
-                if let Some(reject_reason) = client.get_reject_reason() {
-                    error!("Server rejection reason: {}", reject_reason);
-                }
                 retry_count += 1;
-                if retry_count < MAX_RETRIES {
-                    info!("Retrying connection ({}/{})", retry_count, MAX_RETRIES);
-                    std::thread::sleep(std::time::Duration::from_secs(5));
+                // Only a server that did not answer may do better next time.
+                if e.is_timeout() && retry_count < max_attempts {
+                    info!("Retrying connection ({}/{})", retry_count, join_retries);
+                    time::sleep(JOIN_RETRY_DELAY).await;
                 } else {
                     return Err(e.into());
                 }
@@ -92,36 +433,296 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    info!("Waiting for game to start...");
+    if filter.wad_sha1sum.is_none() && filter.deh_sha1sum.is_none() {
+        return Ok(());
+    }
 
-    loop {
-        client.run();
+    // The WAD hashes are only known once the server sends the lobby data.
+    let start = Instant::now();
+    while client.get_client().get_wait_data().is_none() {
+        if start.elapsed() > WAIT_DATA_TIMEOUT || !client.get_client().is_connected() {
+            client.disconnect().await;
+            return Err("No lobby data received from server".into());
+        }
 
-        if let Some(settings) = client.get_settings() {
-            info!("Game started with settings: {:?}", settings);
-            break;
+        client.run(Duration::from_millis(10)).await;
+    }
+
+    if let Some(Err(e)) = client
+        .get_client()
+        .get_wait_data()
+        .map(|wait_data| filter.check_wait_data(wait_data))
+    {
+        client.disconnect().await;
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Runs a bot until its game ends or the process is told to stop, leaving
+/// its server either way. Returns whether it got into a server.
+async fn run_bot(bot: Bot, session: Arc<Session>) -> bool {
+    let name = bot.client_config.player_name.clone();
+    let mut client = match AsyncClient::new(bot.client_config) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to create client: {}", e);
+            return false;
         }
+    };
 
-        thread::sleep(Duration::from_millis(100));
+    if let Some(master) = session.master {
+        client.set_master_server(master);
+    }
+
+    if session.console_log.is_some() {
+        let mut events = client.subscribe();
+        let session = session.clone();
+        // Ends with the client, which closes the channel.
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if let (ClientEvent::ConsoleMessage(message), Some(log)) =
+                    (event, &session.console_log)
+                {
+                    log.write(&name, &message);
+                }
+            }
+        });
     }
 
-    info!("Game started, entering main game loop");
-    game.start_loop();
+    let mut shutdown = session.shutdown.clone();
+    let finished = tokio::select! {
+        joined = play_first_server(&mut client, bot.connect_data, &bot.filter, bot.brain, &session) => {
+            Some(joined)
+        }
+        Ok(_) = shutdown.wait_for(|&stop| stop) => None,
+    };
+
+    let joined = finished.unwrap_or_else(|| {
+        info!("Leaving the server");
+        client.get_client().is_connected()
+    });
+    client.disconnect().await;
+
+    joined
+}
 
-    loop {
-        game.tick(&mut client);
-        client.run();
+/// Joins the first server that lets the bot in and plays until the game
+/// ends. Returns whether it got into a server.
+async fn play_first_server(
+    client: &mut AsyncClient,
+    connect_data: ConnectData,
+    filter: &ServerFilter,
+    brain: Box<dyn Brain>,
+    session: &Session,
+) -> bool {
+    info!("Connecting with data: {:?}", connect_data);
 
-        if !client.is_connected() {
-            info!("Disconnected from server");
-            break;
+    let mut joined = false;
+    for candidate in session.candidates.iter() {
+        if candidate
+            .info
+            .as_ref()
+            .is_some_and(|info| !filter.matches(info))
+        {
+            debug!("Not joining {}: it does not match", candidate.addr);
+            continue;
         }
 
-        thread::sleep(Duration::from_millis(1000 / 35)); // Aim for ~35 FPS
+        match join(
+            client,
+            candidate.addr,
+            connect_data,
+            filter,
+            session.join_retries,
+        )
+        .await
+        {
+            Ok(()) => {
+                joined = true;
+                break;
+            }
+            Err(e) => warn!("Not joining {}: {}", candidate.addr, e),
+        }
     }
 
+    if !joined {
+        return false;
+    }
+
+    if let Some(launch) = session.launch {
+        if let Err(e) = client
+            .start_game_with(launch.players, launch.settings)
+            .await
+        {
+            warn!("Could not start the game: {}", e);
+        }
+    }
+
+    info!("Waiting for game to start...");
+    client.play(brain).await;
     info!("Game loop ended");
-    client.disconnect();
+
+    true
+}
+
+/// Waits for Ctrl-C, or SIGTERM where there is such a thing.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        Ok(()) = signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+
+    let args: Args = argh::from_env();
+    let config = args.config()?;
+
+    let mut wad_file = File::open(&args.iwad)?;
+    let mut wad_contents = Vec::new();
+    wad_file.read_to_end(&mut wad_contents)?;
+    let wad_sha1 = Sha1::digest(&wad_contents);
+
+    info!(
+        sha1 = hex::encode(wad_sha1),
+        "Calculated SHA1 for the WAD file"
+    );
+
+    let bots = args.bots(&config, wad_sha1.into())?;
+    let settings = config.game_settings()?.build();
+    let launch = config
+        .launch_players
+        .map(|players| Launch { players, settings });
+
+    // Servers are resolved to addresses the bots' sockets can reach, so find
+    // out what those get bound to.
+    let local = match args.bind {
+        Some(bind) => bind,
+        None => bind_socket(None)?.local_addr()?,
+    };
+
+    let master = match (&args.master, args.search) {
+        (Some(master), _) => Some(resolve_address_with_port(
+            master,
+            DEFAULT_MASTER_PORT,
+            local,
+        )?),
+        (None, true) => Some(resolve_address(DEFAULT_MASTER_SERVER, local)?),
+        (None, false) => None,
+    };
+
+    let candidates: Arc<[Candidate]> = if args.local_search || args.search {
+        info!("Initializing client");
+        let mut client_config = config.client_config();
+        if let Some(bind) = args.bind {
+            client_config = client_config.bind_addr(bind);
+        }
+        let mut client = Client::new(client_config.build(), Arc::new(MonotonicClock))?;
+        client.init();
+
+        let servers = search(&mut client, &args, master)?;
+        print_servers(&servers);
+
+        if !args.auto_join {
+            return Ok(());
+        }
+
+        // Each bot picks from these the ones that suit it.
+        servers
+            .into_iter()
+            .filter(|server| bots.iter().any(|bot| bot.filter.matches(server)))
+            .map(|server| Candidate {
+                addr: server.addr,
+                info: Some(server),
+            })
+            .collect()
+    } else {
+        match &args.address {
+            Some(address) => Arc::new([Candidate {
+                addr: resolve_address(address, local)?,
+                info: None,
+            }]),
+            None => {
+                return Err(
+                    "No server address given, use --address, --search or --local-search".into(),
+                )
+            }
+        }
+    };
+
+    if candidates.is_empty() {
+        return Err("No joinable server found".into());
+    }
+
+    let join_retries = config.join_retries.unwrap_or(DEFAULT_JOIN_RETRIES);
+
+    let console_log = match &args.console_log {
+        Some(path) => Some(
+            ConsoleLog::open(path)
+                .map_err(|e| format!("Failed to open console log {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    let (stop, shutdown) = watch::channel(false);
+    let session = Arc::new(Session {
+        candidates,
+        master,
+        join_retries,
+        launch,
+        console_log,
+        shutdown: shutdown.clone(),
+    });
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let joined = runtime.block_on(async {
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!("Interrupted, leaving the servers");
+            let _ = stop.send(true);
+
+            shutdown_signal().await;
+            warn!("Interrupted again, exiting without leaving");
+            process::exit(130);
+        });
+
+        let tasks: Vec<_> = bots
+            .into_iter()
+            .map(|bot| {
+                let span = info_span!("bot", name = %bot.client_config.player_name);
+                tokio::spawn(run_bot(bot, session.clone()).instrument(span))
+            })
+            .collect();
+
+        let mut joined = 0;
+        for task in tasks {
+            if task.await.unwrap_or(false) {
+                joined += 1;
+            }
+        }
+        joined
+    });
+
+    if joined == 0 && !*shutdown.borrow() {
+        return Err("Could not join any server".into());
+    }
 
     Ok(())
 }
@@ -0,0 +1,253 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time;
+use tracing::{info, warn};
+
+use super::bind_socket;
+use super::client::{Client, ClientConfig};
+use super::{ClientError, ClientEvent, ClientState, ConnectData, GameSettings, Lobby, TicCmd};
+use crate::brain::Brain;
+use crate::chat::ChatDestination;
+use crate::clock::MonotonicClock;
+use crate::game::{Game, TICRATE};
+use crate::scheduler::{JitterStats, TicScheduler};
+
+/// How long to wait for a packet before checking timers again, like the
+/// blocking client's connect loop does.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A [`Client`] driven by a tokio runtime.
+///
+/// It shares all the protocol handling with [`Client`], but waits for
+/// packets and timers without blocking a thread, so many bots can run as
+/// tasks of a single runtime.
+pub struct AsyncClient {
+    client: Client,
+    socket: UdpSocket,
+}
+
+impl AsyncClient {
+    /// Creates a client and binds its socket. Must be called from within a
+    /// tokio runtime.
+    ///
+    /// Its timers run on the real clock, which tokio waits on: unlike
+    /// [`Client::new`], it takes no other.
+    pub fn new(config: ClientConfig) -> Result<Self, ClientError> {
+        let socket = bind_socket(config.bind_addr)?;
+        socket.set_nonblocking(true)?;
+        let local_addr = socket.local_addr()?;
+        let socket = UdpSocket::from_std(socket)?;
+
+        let mut client = Client::without_socket(config, Arc::new(MonotonicClock), local_addr);
+        client.init();
+
+        Ok(AsyncClient { client, socket })
+    }
+
+    /// The state of the connection.
+    pub fn get_client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns a channel that gets a copy of every event from now on.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<ClientEvent> {
+        self.client.subscribe()
+    }
+
+    /// Sets the master server used to coordinate NAT hole punching.
+    pub fn set_master_server(&mut self, addr: SocketAddr) {
+        self.client.set_master_server(addr);
+    }
+
+    /// Joins the server at `addr`, returning once it lets us into its lobby.
+    pub async fn connect(
+        &mut self,
+        addr: SocketAddr,
+        connect_data: ConnectData,
+    ) -> Result<(), ClientError> {
+        self.client.begin_connect(addr, connect_data);
+
+        loop {
+            let result = self.client.poll_connect();
+            self.flush().await;
+            if let Some(result) = result {
+                return result;
+            }
+
+            self.run(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits for the next event. Returns `None` once disconnected and every
+    /// event has been taken.
+    pub async fn next_event(&mut self) -> Option<ClientEvent> {
+        loop {
+            if let Some(event) = self.client.next_event() {
+                return Some(event);
+            }
+            if !self.client.is_connected() {
+                return None;
+            }
+
+            self.run(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits until the lobby satisfies `condition`, like having a given
+    /// player in it. Returns `None` if the connection ends or the game starts
+    /// first.
+    pub async fn wait_for_lobby(&mut self, condition: impl Fn(&Lobby) -> bool) -> Option<Lobby> {
+        loop {
+            if let Some(lobby) = self.client.get_lobby().filter(|lobby| condition(lobby)) {
+                return Some(lobby);
+            }
+            if !self.client.is_connected() || self.client.get_settings().is_some() {
+                return None;
+            }
+
+            self.run(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Launches the game with `settings`, if we control the lobby.
+    pub async fn start_game(&mut self, settings: GameSettings) -> Result<(), ClientError> {
+        let result = self.client.start_game(settings);
+        self.flush().await;
+        result
+    }
+
+    /// Waits until the server counts at least `players` players in the lobby
+    /// as ready and starts the game with `settings`, if we are the
+    /// controller by then. Returns early if someone else starts the game or
+    /// the connection ends.
+    pub async fn start_game_with(
+        &mut self,
+        players: usize,
+        settings: GameSettings,
+    ) -> Result<(), ClientError> {
+        let ready = |lobby: &Lobby| lobby.is_controller && lobby.ready_players >= players;
+        match self.wait_for_lobby(ready).await {
+            Some(lobby) => {
+                info!(
+                    "{} players ready in the lobby, starting the game",
+                    lobby.ready_players
+                );
+                self.start_game(settings).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Says `text` in the game chat, as the game loop sends our commands.
+    pub fn send_chat(&mut self, to: ChatDestination, text: &str) {
+        self.client.send_chat(to, text);
+    }
+
+    /// Sends the local player's command for tic `maketic`.
+    pub async fn send_ticcmd(&mut self, ticcmd: &TicCmd, maketic: u32) {
+        self.client.send_ticcmd(ticcmd, maketic);
+        self.flush().await;
+    }
+
+    /// Runs one iteration of the game loop, sending the commands it built.
+    pub async fn tick(&mut self, game: &mut Game) {
+        game.tick(&mut self.client);
+        self.flush().await;
+    }
+
+    /// Waits for the game to start and plays it with `brain` until the
+    /// connection ends. Returns how well the tics were kept on time, which
+    /// is also logged every minute.
+    pub async fn play(&mut self, brain: Box<dyn Brain>) -> JitterStats {
+        // The game may have started while someone else took the events.
+        let settings = loop {
+            if let Some(settings) = self.client.get_settings() {
+                break settings;
+            }
+
+            match self.next_event().await {
+                Some(ClientEvent::GameStarted(settings)) => break settings,
+                Some(_) => {}
+                None => return JitterStats::default(),
+            }
+        };
+
+        info!("Game started with settings: {:?}", settings);
+        let clock = self.client.get_clock();
+        let mut game = Game::new(&settings, clock.clone(), brain);
+        game.start_loop();
+
+        let mut scheduler = TicScheduler::new(clock.clone(), TICRATE);
+        let mut last_stats_report = clock.now();
+        while self.client.is_connected() {
+            // Wake up on the next tic, or earlier if the server sends something.
+            let received = self.run(scheduler.until_next_tic()).await;
+
+            // Tics the game was waiting on run as soon as they arrive.
+            if scheduler.poll() || received {
+                self.tick(&mut game).await;
+            }
+
+            if clock.elapsed(last_stats_report) >= STATS_REPORT_INTERVAL {
+                last_stats_report = clock.now();
+                scheduler.get_stats().report();
+            }
+        }
+
+        info!("Disconnected from server");
+        scheduler.get_stats().report();
+        *scheduler.get_stats()
+    }
+
+    /// Tells the server we are leaving, waiting until it acknowledges or
+    /// the attempt times out.
+    pub async fn disconnect(&mut self) {
+        self.client.begin_disconnect();
+        self.flush().await;
+
+        while self.client.get_state() == ClientState::Disconnecting {
+            self.run(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Handles the next packet, or whatever is due after `timeout` passes
+    /// without one. Returns whether the server sent anything.
+    pub async fn run(&mut self, timeout: Duration) -> bool {
+        let mut buf = [0u8; 4096];
+        let mut received = false;
+        match time::timeout(timeout, self.socket.recv_from(&mut buf)).await {
+            Ok(Ok((size, addr))) => received |= self.client.handle_datagram(&buf[..size], addr),
+            Ok(Err(e)) => warn!("Failed to receive packet: {}", e),
+            Err(_) => {}
+        }
+        // Take whatever else arrived meanwhile before running timers.
+        loop {
+            match self.socket.try_recv_from(&mut buf) {
+                Ok((size, addr)) => received |= self.client.handle_datagram(&buf[..size], addr),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to receive packet: {}", e);
+                    break;
+                }
+            }
+        }
+
+        self.client.run();
+        self.flush().await;
+        received
+    }
+
+    async fn flush(&mut self) {
+        for (data, addr) in self.client.take_outgoing() {
+            if let Err(e) = self.socket.send_to(&data, addr).await {
+                warn!("Failed to send packet to {}: {}", addr, e);
+            }
+        }
+    }
+}
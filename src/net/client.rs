@@ -1,41 +1,165 @@
 use rand::prelude::*;
-use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{io, thread};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, info, warn};
 
 use super::packet::Packet;
 use super::*;
+use crate::chat::{ChatAssembler, ChatDestination, ChatMessage, ChatQueue};
+use crate::clock::Clock;
 
 const KEEPALIVE_PERIOD: Duration = Duration::from_secs(1);
-const CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
-const MAX_RETRIES: u32 = 10;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ClientState {
-    Disconnected,
-    Connecting,
-    Connected,
-    WaitingLaunch,
-    WaitingStart,
-    InGame,
-    Disconnecting,
+const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_RETRIES: u32 = 10;
+const DEFAULT_GAME_DESCRIPTION: &str = "Chocolate Doom 3.0.1";
+const QUERY_SEND_INTERVAL: Duration = Duration::from_millis(500);
+const QUERY_MAX_ATTEMPTS: u32 = 3;
+const SYN_SEND_INTERVAL: Duration = Duration::from_secs(1);
+const RELIABLE_RESEND_INTERVAL: Duration = Duration::from_secs(1);
+const HOLE_PUNCH_DELAY: Duration = Duration::from_secs(2);
+const DISCONNECT_RESEND_INTERVAL: Duration = Duration::from_secs(1);
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CLOCK_OFFSET_MS: i32 = 1000;
+// Events nobody takes are dropped, oldest first, past this many.
+pub(crate) const MAX_QUEUED_EVENTS: usize = 256;
+
+/// A packet's data and where it is going.
+pub(crate) type Datagram = (Vec<u8>, SocketAddr);
+
+/// How the client presents itself to servers and how hard it tries to
+/// reach them.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// The name shown to other players. When empty, one is picked from the
+    /// environment.
+    pub player_name: String,
+    /// Whether the client expects to spectate. The server has the final
+    /// word, through the [`ConnectData`] sent when connecting.
+    pub drone: bool,
+    /// The version string sent to the server, which warns about mismatches.
+    pub game_description: String,
+    /// How long to wait for a server to let us in.
+    pub connection_timeout: Duration,
+    /// How many SYN packets to send before giving up on a server.
+    pub max_retries: u32,
+    /// The local address to send from. By default every interface is used,
+    /// over both IPv4 and IPv6 where available.
+    pub bind_addr: Option<SocketAddr>,
 }
 
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            player_name: "HydraBot".to_string(),
+            drone: true,
+            game_description: DEFAULT_GAME_DESCRIPTION.to_string(),
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            bind_addr: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Starts from the defaults.
+    pub fn builder() -> ClientConfigBuilder {
+        ClientConfigBuilder::default()
+    }
+}
+
+/// Builds a [`ClientConfig`], leaving everything not set at its default.
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
+}
+
+impl ClientConfigBuilder {
+    /// The name shown to the other players.
+    pub fn player_name(mut self, player_name: impl Into<String>) -> Self {
+        self.config.player_name = player_name.into();
+        self
+    }
+
+    /// Joins as a spectator instead of taking a player slot.
+    pub fn drone(mut self, drone: bool) -> Self {
+        self.config.drone = drone;
+        self
+    }
+
+    /// What we tell servers and the master server we are running.
+    pub fn game_description(mut self, game_description: impl Into<String>) -> Self {
+        self.config.game_description = game_description.into();
+        self
+    }
+
+    /// How long to keep trying to get into a server.
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Self {
+        self.config.connection_timeout = connection_timeout;
+        self
+    }
+
+    /// How many times to resend the connection request to a silent server.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.config.max_retries = max_retries;
+        self
+    }
+
+    /// The local address to bind to, instead of any free port.
+    pub fn bind_addr(mut self, bind_addr: SocketAddr) -> Self {
+        self.config.bind_addr = Some(bind_addr);
+        self
+    }
+
+    /// Finishes the configuration.
+    pub fn build(self) -> ClientConfig {
+        self.config
+    }
+}
+
+/// A connection to a Chocolate Doom server.
+///
+/// Nothing happens in the background: [`Client::run`] has to be called
+/// regularly to process incoming packets, keep the connection alive and
+/// resend what got lost.
 pub struct Client {
-    socket: UdpSocket,
+    // None when the async client owns the socket and does the sending.
+    socket: Option<UdpSocket>,
+    local_addr: SocketAddr,
+    clock: Arc<dyn Clock>,
     state: ClientState,
     server_addr: Option<SocketAddr>,
+    master_addr: Option<SocketAddr>,
+    connect_data: ConnectData,
     settings: Option<GameSettings>,
-    reject_reason: Option<String>,
-    player_name: String,
+    connect_error: Option<ClientError>,
+    config: ClientConfig,
     drone: bool,
     recv_window_start: u32,
     recv_window: [ServerRecv; BACKUPTICS],
+    received_tics: VecDeque<ReceivedTic>,
+    chat: ChatAssembler,
+    chat_queue: ChatQueue,
+    // Console messages the game loop has yet to show the brain.
+    console_messages: VecDeque<ConsoleMessage>,
+    events: VecDeque<ClientEvent>,
+    subscribers: Vec<UnboundedSender<ClientEvent>>,
+    // Packets for the async client to send, when it owns the socket.
+    outgoing: Vec<Datagram>,
     send_queue: [ServerSend; BACKUPTICS],
+    send_window_start: u32,
     need_acknowledge: bool,
+    reliable_recv_seq: u8,
+    reliable_send_seq: u8,
+    outgoing_reliables: VecDeque<OutgoingReliable>,
+    // What to start the game with once the server confirms our launch.
+    pending_start: Option<GameSettings>,
     gamedata_recv_time: Instant,
     last_latency: i32,
+    offset_ms: i32,
     net_local_wad_sha1sum: [u8; 20],
     net_local_deh_sha1sum: [u8; 20],
     net_local_is_freedoom: bool,
@@ -48,14 +172,25 @@ pub struct Client {
     recvwindow_cmd_base: [TicCmd; NET_MAXPLAYERS],
     start_time: Instant,
     num_retries: u32,
+    last_syn_time: Option<Instant>,
+    last_disconnect_time: Option<Instant>,
+    hole_punch_requested: bool,
     protocol: Protocol,
-    gamemode: i32,
+    gamemode: GameMode,
     gamemission: i32,
     lowres_turn: i32,
     max_players: i32,
     is_freedoom: i32,
     player_class: i32,
     pid_controller: PIDController,
+    rtt_estimator: RttEstimator,
+}
+
+/// A reliable packet waiting for the server to acknowledge it.
+struct OutgoingReliable {
+    seq: u8,
+    packet: Packet,
+    last_send_time: Option<Instant>,
 }
 
 struct PIDController {
@@ -79,37 +214,117 @@ impl PIDController {
 
     fn update(&mut self, error: i32) -> i32 {
         self.cumul_error += error;
-        let d_error = error - self.last_error;
+        let d_error = self.last_error - error;
         self.last_error = error;
 
         (self.kp * error as f32 - self.ki * self.cumul_error as f32 + self.kd * d_error as f32)
             as i32
     }
+
+    fn reset(&mut self) {
+        self.cumul_error = 0;
+        self.last_error = 0;
+    }
+}
+
+/// Exponentially weighted moving average of the round trip time, in the
+/// same spirit as TCP's smoothed RTT.
+struct RttEstimator {
+    alpha: f32,
+    smoothed_ms: Option<f32>,
+}
+
+impl RttEstimator {
+    fn new(alpha: f32) -> Self {
+        RttEstimator {
+            alpha,
+            smoothed_ms: None,
+        }
+    }
+
+    fn update(&mut self, sample: Duration) -> i32 {
+        let sample_ms = sample.as_secs_f32() * 1000.0;
+        let smoothed = match self.smoothed_ms {
+            Some(smoothed) => smoothed + self.alpha * (sample_ms - smoothed),
+            None => sample_ms,
+        };
+        self.smoothed_ms = Some(smoothed);
+
+        smoothed as i32
+    }
+
+    fn reset(&mut self) {
+        self.smoothed_ms = None;
+    }
 }
 
 impl Client {
-    pub fn new(player_name: String, drone: bool) -> io::Result<Self> {
+    /// Creates a client and binds its socket. It does not talk to anyone
+    /// until told to.
+    pub fn new(config: ClientConfig, clock: Arc<dyn Clock>) -> Result<Self, ClientError> {
+        let socket = bind_socket(config.bind_addr)?;
+        socket.set_nonblocking(true)?;
+        let local_addr = socket.local_addr()?;
+
+        Ok(Self::with_socket(config, clock, Some(socket), local_addr))
+    }
+
+    /// Creates a client for a socket bound on `local_addr` that someone else
+    /// reads and writes. Packets are handed in with
+    /// [`Client::handle_datagram`] and the ones to send are taken with
+    /// [`Client::take_outgoing`].
+    pub(crate) fn without_socket(
+        config: ClientConfig,
+        clock: Arc<dyn Clock>,
+        local_addr: SocketAddr,
+    ) -> Self {
+        Self::with_socket(config, clock, None, local_addr)
+    }
+
+    fn with_socket(
+        config: ClientConfig,
+        clock: Arc<dyn Clock>,
+        socket: Option<UdpSocket>,
+        local_addr: SocketAddr,
+    ) -> Self {
         info!(
             "Creating new Client: player_name={}, drone={}",
-            player_name, drone
+            config.player_name, config.drone
         );
+        info!("Bound to {}", local_addr);
 
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
-        socket.set_nonblocking(true)?;
-        Ok(Client {
+        let now = clock.now();
+        Client {
             socket,
+            local_addr,
+            clock,
             state: ClientState::Disconnected,
             server_addr: None,
+            master_addr: None,
+            connect_data: ConnectData::default(),
             settings: None,
-            reject_reason: None,
-            player_name,
-            drone,
+            connect_error: None,
+            drone: config.drone,
+            config,
             recv_window_start: 0,
-            recv_window: [ServerRecv::default(); BACKUPTICS],
-            send_queue: [ServerSend::default(); BACKUPTICS],
+            recv_window: [ServerRecv::new(now); BACKUPTICS],
+            received_tics: VecDeque::new(),
+            chat: ChatAssembler::default(),
+            chat_queue: ChatQueue::default(),
+            console_messages: VecDeque::new(),
+            events: VecDeque::new(),
+            subscribers: Vec::new(),
+            outgoing: Vec::new(),
+            send_queue: [ServerSend::new(now); BACKUPTICS],
+            send_window_start: 0,
             need_acknowledge: false,
-            gamedata_recv_time: Instant::now(),
+            reliable_recv_seq: 0,
+            reliable_send_seq: 0,
+            outgoing_reliables: VecDeque::new(),
+            pending_start: None,
+            gamedata_recv_time: now,
             last_latency: 0,
+            offset_ms: 0,
             net_local_wad_sha1sum: [0; 20],
             net_local_deh_sha1sum: [0; 20],
             net_local_is_freedoom: false,
@@ -117,26 +332,28 @@ impl Client {
             net_client_connected: false,
             net_client_received_wait_data: false,
             net_client_wait_data: WaitData::default(),
-            last_send_time: Instant::now(),
+            last_send_time: now,
             last_ticcmd: TicCmd::default(),
             recvwindow_cmd_base: [TicCmd::default(); NET_MAXPLAYERS],
             num_retries: 0,
-            start_time: Instant::now(),
+            last_syn_time: None,
+            last_disconnect_time: None,
+            hole_punch_requested: false,
+            start_time: now,
             protocol: Protocol::ChocolateDoom0,
-            gamemode: 0,
+            gamemode: GameMode::Indetermined,
             gamemission: 0,
             lowres_turn: 0,
             max_players: 0,
             is_freedoom: 0,
             player_class: 0,
             pid_controller: PIDController::new(0.1, 0.01, 0.02),
-        })
-    }
-
-    pub fn get_reject_reason(&self) -> Option<&str> {
-        self.reject_reason.as_deref()
+            rtt_estimator: RttEstimator::new(0.125),
+        }
     }
 
+    /// Resets the connection state and picks a player name if none was
+    /// configured.
     pub fn init(&mut self) {
         debug!("Initializing Client");
         self.init_bot();
@@ -144,10 +361,10 @@ impl Client {
         self.net_client_received_wait_data = false;
         self.net_waiting_for_launch = false;
 
-        if self.player_name.is_empty() {
-            self.player_name = Self::get_player_name();
+        if self.config.player_name.is_empty() {
+            self.config.player_name = Self::get_player_name();
         }
-        debug!("Player name set to: {}", self.player_name);
+        debug!("Player name set to: {}", self.config.player_name);
     }
 
     fn init_bot(&mut self) {
@@ -157,9 +374,8 @@ impl Client {
     }
 
     fn get_player_name() -> String {
-        std::env::args()
-            .nth(1)
-            .or_else(|| std::env::var("USER").ok())
+        std::env::var("USER")
+            .ok()
             .or_else(|| std::env::var("USERNAME").ok())
             .unwrap_or_else(Self::get_random_pet_name)
     }
@@ -170,27 +386,40 @@ impl Client {
         pet_names.choose(&mut rng).unwrap_or(&"Player").to_string()
     }
 
+    /// Processes whatever the server sent and sends what is due.
     pub fn run(&mut self) {
-        self.run_bot();
         self.receive_packets();
         self.handle_state();
         self.send_keepalive();
+        self.send_reliables();
         self.check_resends();
     }
 
     fn receive_packets(&mut self) {
         let mut buf = [0u8; 4096];
-        while let Ok((size, addr)) = self.socket.recv_from(&mut buf) {
+        while let Ok((size, addr)) = self.recv_from(&mut buf) {
             debug!("Received {} bytes from {:?}", size, addr);
-            let packet_data = buf[..size].to_vec();
-            let mut packet = Packet {
-                data: packet_data,
-                pos: 0,
-            };
-            self.parse_packet(&mut packet);
+            self.handle_datagram(&buf[..size], addr);
         }
     }
 
+    /// Processes a packet that was read off the socket elsewhere. Only the
+    /// server we connected to is listened to, so returns whether it sent it.
+    pub(crate) fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) -> bool {
+        // Dual-stack sockets see IPv4 senders as mapped IPv6 addresses.
+        let from_server = self.server_addr.is_some_and(|server| {
+            server.ip().to_canonical() == addr.ip().to_canonical() && server.port() == addr.port()
+        });
+        if !from_server {
+            debug!("Ignoring packet from {:?}", addr);
+            return false;
+        }
+
+        let mut packet = Packet::from_data(data);
+        self.parse_packet(&mut packet);
+        true
+    }
+
     fn handle_state(&mut self) {
         match self.state {
             ClientState::Connecting => self.handle_connecting(),
@@ -202,9 +431,9 @@ impl Client {
     }
 
     fn handle_connecting(&mut self) {
-        let elapsed = self.start_time.elapsed();
+        let elapsed = self.clock.elapsed(self.start_time);
         debug!("Connecting... Time elapsed: {:?}", elapsed);
-        if elapsed > CONNECTION_TIMEOUT {
+        if elapsed > self.config.connection_timeout {
             self.handle_connection_timeout();
         }
     }
@@ -219,44 +448,95 @@ impl Client {
     }
 
     fn handle_disconnecting(&mut self) {
-        if self.start_time.elapsed() > Duration::from_secs(5) {
+        if self.clock.elapsed(self.start_time) > DISCONNECT_TIMEOUT {
             self.handle_disconnection_timeout();
+        } else if self
+            .last_disconnect_time
+            .is_none_or(|time| self.clock.elapsed(time) > DISCONNECT_RESEND_INTERVAL)
+        {
+            self.send_disconnect();
         }
     }
 
     fn handle_connection_timeout(&mut self) {
         warn!("Connection attempt timed out");
-        self.reject_reason = Some("Connection attempt timed out".to_string());
-        self.state = ClientState::Disconnected;
+        if let Some(addr) = self.server_addr {
+            self.connect_error = Some(ClientError::Timeout {
+                addr,
+                timeout: self.config.connection_timeout,
+            });
+        }
+        self.set_state(ClientState::Disconnected);
         self.shutdown();
     }
 
     fn handle_disconnection_timeout(&mut self) {
         warn!("Disconnection timed out");
-        self.state = ClientState::Disconnected;
+        self.set_state(ClientState::Disconnected);
         self.shutdown();
     }
 
     fn send_keepalive(&mut self) {
-        if (self.state == ClientState::Connected || self.state == ClientState::InGame)
-            && self.last_send_time.elapsed() > KEEPALIVE_PERIOD
-        {
+        let connected = matches!(
+            self.state,
+            ClientState::Connected
+                | ClientState::WaitingLaunch
+                | ClientState::WaitingStart
+                | ClientState::InGame
+        );
+
+        if connected && self.clock.elapsed(self.last_send_time) > KEEPALIVE_PERIOD {
             let mut packet = Packet::new();
-            packet.write_u16(PacketType::GameDataAck.to_u16());
-            packet.write_u8((self.recv_window_start & 0xff) as u8);
+            packet.write_u16(PacketType::KeepAlive.to_u16());
             self.send_packet(&packet);
-            self.last_send_time = Instant::now();
+            self.last_send_time = self.clock.now();
         }
     }
 
+    fn set_state(&mut self, state: ClientState) {
+        if state == self.state {
+            return;
+        }
+
+        debug!("State changed from {:?} to {:?}", self.state, state);
+        let from = std::mem::replace(&mut self.state, state);
+        self.emit(ClientEvent::StateChanged { from, to: state });
+    }
+
+    fn emit(&mut self, event: ClientEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
     fn shutdown(&mut self) {
-        self.state = ClientState::Disconnected;
+        if self.net_client_connected {
+            self.emit(ClientEvent::Disconnected);
+        }
+        self.set_state(ClientState::Disconnected);
         self.net_client_connected = false;
     }
 
     fn parse_packet(&mut self, packet: &mut Packet) {
-        let original_data = packet.data.clone();
-        if let Some(packet_type) = packet.read_u16().and_then(PacketType::from_u16) {
+        let original_data = packet.data().to_vec();
+        let Some(mut packet_type) = packet.read_u16() else {
+            warn!("Packet too short: {:x?}", original_data);
+            return;
+        };
+
+        if packet_type & NET_RELIABLE_PACKET != 0 {
+            if !self.receive_reliable(packet) {
+                debug!("Discarding out of sequence reliable packet");
+                return;
+            }
+            packet_type &= !NET_RELIABLE_PACKET;
+        }
+
+        if let Some(packet_type) = PacketType::from_u16(packet_type) {
             debug!(
                 "Received packet: type={:?}, data={:x?}",
                 packet_type, original_data
@@ -268,11 +548,14 @@ impl Client {
                 PacketType::Launch => self.parse_launch(packet),
                 PacketType::GameStart => self.parse_game_start(packet),
                 PacketType::GameData => self.parse_game_data(packet),
+                PacketType::GameDataAck => self.parse_game_data_ack(packet),
                 PacketType::GameDataResend => self.parse_resend_request(packet),
                 PacketType::ConsoleMessage => self.parse_console_message(packet),
                 PacketType::Disconnect => self.parse_disconnect(packet),
                 PacketType::DisconnectAck => self.parse_disconnect_ack(packet),
+                PacketType::ReliableAck => self.parse_reliable_ack(packet),
                 PacketType::KeepAlive => debug!("Received keep-alive packet"),
+                PacketType::NatHolePunch => self.parse_nat_hole_punch(packet),
                 _ => warn!("Unhandled packet type: {:?}", packet_type),
             }
         } else {
@@ -280,86 +563,178 @@ impl Client {
         }
     }
 
-    fn parse_disconnect(&mut self, packet: &mut Packet) {
+    /// Reads the sequence number of a reliable packet and acknowledges it.
+    /// Returns whether the packet is the next one expected and should be
+    /// processed.
+    fn receive_reliable(&mut self, packet: &mut Packet) -> bool {
+        let Some(seq) = packet.read_u8() else {
+            return false;
+        };
+
+        let in_sequence = seq == self.reliable_recv_seq;
+        if in_sequence {
+            self.reliable_recv_seq = self.reliable_recv_seq.wrapping_add(1);
+        }
+
+        // The ack is sent even for out of sequence packets, as the server
+        // may have missed the previous one.
+        let mut ack = Packet::new();
+        ack.write_u16(PacketType::ReliableAck.to_u16());
+        ack.write_u8(self.reliable_recv_seq);
+        self.send_packet(&ack);
+
+        in_sequence
+    }
+
+    /// Queues a reliable packet, written by `write` after the header. Like
+    /// Chocolate Doom, only the oldest unacknowledged one is in flight.
+    fn send_reliable(&mut self, packet_type: PacketType, write: impl FnOnce(&mut Packet)) {
+        let mut packet = Packet::new();
+        packet.write_u16(packet_type.to_u16() | NET_RELIABLE_PACKET);
+        packet.write_u8(self.reliable_send_seq);
+        write(&mut packet);
+
+        self.outgoing_reliables.push_back(OutgoingReliable {
+            seq: self.reliable_send_seq,
+            packet,
+            last_send_time: None,
+        });
+        self.reliable_send_seq = self.reliable_send_seq.wrapping_add(1);
+        self.send_reliables();
+    }
+
+    /// Sends the oldest unacknowledged reliable packet, if it has not been
+    /// sent yet or its last copy went unanswered.
+    fn send_reliables(&mut self) {
+        let now = self.clock.now();
+        let Some(reliable) = self.outgoing_reliables.front_mut() else {
+            return;
+        };
+        if reliable
+            .last_send_time
+            .is_some_and(|time| now.saturating_duration_since(time) < RELIABLE_RESEND_INTERVAL)
+        {
+            return;
+        }
+
+        reliable.last_send_time = Some(now);
+        let packet = reliable.packet.clone();
+        self.send_packet(&packet);
+    }
+
+    fn parse_reliable_ack(&mut self, packet: &mut Packet) {
+        let Some(seq) = packet.read_u8() else {
+            return;
+        };
+
+        // The ack carries the next sequence number the server expects.
+        if self
+            .outgoing_reliables
+            .front()
+            .is_some_and(|reliable| reliable.seq.wrapping_add(1) == seq)
+        {
+            self.outgoing_reliables.pop_front();
+            self.send_reliables();
+        }
+    }
+
+    fn parse_disconnect(&mut self, _packet: &mut Packet) {
         info!("Received disconnect request from server");
-        self.send_disconnect_ack(packet);
-        self.state = ClientState::Disconnected;
+        self.send_disconnect_ack();
+        self.set_state(ClientState::Disconnected);
         self.shutdown();
     }
 
     fn parse_disconnect_ack(&mut self, _packet: &mut Packet) {
         if self.state == ClientState::Disconnecting {
             info!("Received disconnect acknowledgement");
-            self.state = ClientState::Disconnected;
+            self.set_state(ClientState::Disconnected);
             self.shutdown();
         }
     }
 
+    fn parse_nat_hole_punch(&mut self, _packet: &mut Packet) {
+        if self.state != ClientState::Connecting {
+            debug!("Ignoring NAT hole punch in state {:?}", self.state);
+            return;
+        }
+
+        // The server has opened its side of the NAT towards us, so a SYN
+        // sent now should get through without waiting for the next retry.
+        info!("Received NAT hole punch from server, resending SYN");
+        self.send_syn();
+    }
+
     fn parse_syn(&mut self, packet: &mut Packet) {
         debug!("Processing SYN response");
+        if self.state != ClientState::Connecting {
+            debug!("Ignoring SYN response in state {:?}", self.state);
+            return;
+        }
+
         let server_version = packet.read_safe_string().unwrap_or_default();
         debug!("Server version: {}", server_version);
 
-        if let Some(protocol) = self.negotiate_protocol(packet) {
+        let protocol = packet.read_protocol();
+        if protocol != Protocol::Unknown {
             self.protocol = protocol;
             info!("Connected to server");
-            self.state = ClientState::Connected;
-
-            // Send an ACK packet in response to the SYN
-            self.send_ack(packet);
-
-            if server_version != env!("CARGO_PKG_VERSION") {
+            // The game start can follow in the same batch of packets, so the
+            // connection is set up here rather than once connect() sees it.
+            self.connect_error = None;
+            self.set_state(ClientState::WaitingLaunch);
+            self.drone = self.connect_data.drone != 0;
+            self.net_client_connected = true;
+
+            if server_version != self.config.game_description {
                 warn!(
                     "Version mismatch: Client is '{}', but the server is '{}'. \
                     This mismatch may cause the game to desynchronize.",
-                    env!("CARGO_PKG_VERSION"),
-                    server_version
+                    self.config.game_description, server_version
                 );
             }
         } else {
             error!("No common protocol");
-            self.reject_reason = Some("No common protocol".to_string());
-        }
-    }
-
-    fn send_ack(&mut self, packet: &mut Packet) {
-        packet.write_u16(PacketType::Ack.to_u16());
-        packet.write_protocol(self.protocol);
-        self.send_packet(&packet);
-        info!("ACK sent to server");
-    }
-
-    fn negotiate_protocol(&self, packet: &mut Packet) -> Option<Protocol> {
-        let num_protocols = packet.read_u8().unwrap_or(0);
-        for _ in 0..num_protocols {
-            let protocol = packet.read_protocol();
-            if protocol == Protocol::ChocolateDoom0 {
-                return Some(protocol);
-            }
+            self.connect_error = Some(ClientError::Protocol);
+            self.shutdown();
         }
-        None
     }
 
     fn parse_reject(&mut self, packet: &mut Packet) {
         if self.state == ClientState::Connecting {
             if let Some(msg) = packet.read_safe_string() {
                 warn!("Connection rejected: {}", msg);
-                self.state = ClientState::Disconnected;
-                self.reject_reason = Some(msg);
+                self.emit(ClientEvent::Rejected(msg.clone()));
+                self.set_state(ClientState::Disconnected);
+                self.connect_error = Some(ClientError::Rejected(msg));
                 self.shutdown();
             }
         }
     }
 
-    fn send_disconnect_ack(&self, packet: &mut Packet) {
+    fn send_disconnect(&mut self) {
+        let mut packet = Packet::new();
+        packet.write_u16(PacketType::Disconnect.to_u16());
+        self.send_packet(&packet);
+        self.last_disconnect_time = Some(self.clock.now());
+    }
+
+    fn send_disconnect_ack(&mut self) {
+        let mut packet = Packet::new();
         packet.write_u16(PacketType::DisconnectAck.to_u16());
-        packet.write_u32(0x80);
         self.send_packet(&packet);
     }
 
     fn parse_waiting_data(&mut self, packet: &mut Packet) {
         if let Some(wait_data) = packet.read_wait_data() {
             if self.validate_wait_data(&wait_data) {
+                if !self.net_client_received_wait_data {
+                    self.check_sha1_sums(&wait_data);
+                }
+                // The server repeats the lobby data for every keepalive.
+                if !self.net_client_received_wait_data || wait_data != self.net_client_wait_data {
+                    self.update_lobby(&wait_data);
+                }
                 self.net_client_wait_data = wait_data;
                 self.net_client_received_wait_data = true;
 
@@ -367,11 +742,56 @@ impl Client {
 
                 self.max_players = self.net_client_wait_data.max_players;
                 self.is_freedoom = self.net_client_wait_data.is_freedoom;
+            }
+        }
+    }
+
+    // Like Chocolate Doom, a server running other files is only warned
+    // about, once per connection.
+    fn check_sha1_sums(&mut self, wait_data: &WaitData) {
+        let wad = wait_data.wad_sha1sum != self.connect_data.wad_sha1sum;
+        let deh = wait_data.deh_sha1sum != self.connect_data.deh_sha1sum;
+        if wad {
+            warn!("Server is running a different WAD");
+        }
+        if deh {
+            warn!("Server is running a different DEH");
+        }
+        if wad || deh {
+            self.emit(ClientEvent::Sha1Mismatch { wad, deh });
+        }
+    }
 
-                // Send an ACK in response to waiting data
-                self.send_ack(packet);
+    fn update_lobby(&mut self, wait_data: &WaitData) {
+        let lobby = Lobby::from_wait_data(wait_data);
+        let old_players = self.get_lobby().map(|old| old.players).unwrap_or_default();
+
+        // Players move up a slot when someone before them leaves, so they
+        // are told apart by name and address. Several players can share
+        // both, so each one still in the lobby accounts for only one of the
+        // old ones.
+        let same = |a: &LobbyPlayer, b: &LobbyPlayer| a.name == b.name && a.address == b.address;
+        let mut joined: Vec<&LobbyPlayer> = lobby.players.iter().collect();
+        let mut left = Vec::new();
+        for player in &old_players {
+            match joined.iter().position(|other| same(player, other)) {
+                Some(i) => {
+                    joined.remove(i);
+                }
+                None => left.push(player),
             }
         }
+
+        for player in left {
+            info!("{} left the lobby", player.name);
+            self.emit(ClientEvent::PlayerLeft(player.clone()));
+        }
+        for player in joined {
+            info!("{} joined the lobby", player.name);
+            self.emit(ClientEvent::PlayerJoined(player.clone()));
+        }
+
+        self.emit(ClientEvent::LobbyUpdated(lobby));
     }
 
     fn validate_wait_data(&self, wait_data: &WaitData) -> bool {
@@ -387,12 +807,18 @@ impl Client {
         debug!("Processing launch packet");
         if self.state == ClientState::WaitingLaunch {
             if let Some(num_players) = packet.read_u8() {
+                if num_players as usize > NET_MAXPLAYERS {
+                    warn!("Ignoring launch for {} players", num_players);
+                    return;
+                }
+
                 self.net_client_wait_data.num_players = num_players as i32;
-                self.state = ClientState::WaitingStart;
+                self.set_state(ClientState::WaitingStart);
                 info!("Now waiting to start the game");
 
-                // Send a response to confirm receipt of launch packet
-                self.send_launch_response();
+                if let Some(settings) = self.pending_start.take() {
+                    self.send_game_start(settings);
+                }
             }
         } else {
             warn!(
@@ -402,52 +828,76 @@ impl Client {
         }
     }
 
-    fn send_launch_response(&mut self) {
-        let mut packet = Packet::new();
-        packet.write_u16(PacketType::Launch.to_u16());
-        self.send_packet(&packet);
-        info!("Launch response sent to server");
-    }
-
     fn parse_game_start(&mut self, packet: &mut Packet) {
         debug!("Processing game start packet");
+        if self.state == ClientState::Disconnecting {
+            debug!("Ignoring game start while disconnecting");
+            return;
+        }
 
         if let Some(settings) = packet.read_settings() {
             if self.validate_game_settings(&settings) {
                 info!("Initiating game state with settings: {:?}", settings);
-                self.state = ClientState::InGame;
+                self.set_state(ClientState::InGame);
                 self.settings = Some(settings);
                 self.init_game_state();
+                self.emit(ClientEvent::GameStarted(settings));
 
                 self.lowres_turn = settings.lowres_turn;
-                self.player_class = settings.player_classes[settings.consoleplayer as usize];
-
-                // Send an ACK in response to game start
-                self.send_ack(packet);
+                if settings.consoleplayer >= 0 {
+                    self.player_class = settings.player_classes[settings.consoleplayer as usize];
+                }
             }
         }
     }
 
+    fn send_game_start(&mut self, mut settings: GameSettings) {
+        // The server assigns the players, but still reads their classes.
+        settings.num_players = self.net_client_wait_data.num_players;
+        settings.consoleplayer = self.net_client_wait_data.consoleplayer;
+        settings.player_classes = [self.player_class; NET_MAXPLAYERS];
+
+        info!("Starting the game with settings: {:?}", settings);
+        self.send_reliable(PacketType::GameStart, |packet| {
+            packet.write_settings(&settings)
+        });
+    }
+
     fn validate_game_settings(&self, settings: &GameSettings) -> bool {
         settings.num_players <= NET_MAXPLAYERS as i32
-            && (settings.consoleplayer as usize) < settings.num_players as usize
+            && settings.consoleplayer < settings.num_players
             && ((self.drone && settings.consoleplayer < 0)
                 || (!self.drone && settings.consoleplayer >= 0))
     }
 
     fn init_game_state(&mut self) {
         self.recv_window_start = 0;
-        self.recv_window = [ServerRecv::default(); BACKUPTICS];
-        self.send_queue = [ServerSend::default(); BACKUPTICS];
+        let now = self.clock.now();
+        self.recv_window = [ServerRecv::new(now); BACKUPTICS];
+        self.received_tics.clear();
+        self.chat = ChatAssembler::default();
+        self.recvwindow_cmd_base = [TicCmd::default(); NET_MAXPLAYERS];
+        self.last_ticcmd = TicCmd::default();
+        self.send_queue = [ServerSend::new(now); BACKUPTICS];
+        self.send_window_start = 0;
+        self.last_latency = 0;
+        self.rtt_estimator.reset();
+        self.pid_controller.reset();
+        self.offset_ms = 0;
     }
 
     fn parse_game_data(&mut self, packet: &mut Packet) {
         debug!("Processing game data packet");
+        if self.state != ClientState::InGame {
+            debug!("Ignoring game data in state {:?}", self.state);
+            return;
+        }
+
         if let (Some(seq), Some(num_tics)) = (packet.read_u8(), packet.read_u8()) {
-            let seq = self.expand_tic_num(seq as u32);
+            let seq = Self::expand_tic_num(self.recv_window_start, seq as u32);
             debug!("Game data received, seq={}, num_tics={}", seq, num_tics);
 
-            let lowres_turn = self.settings.as_ref().map_or(false, |s| s.lowres_turn != 0);
+            let lowres_turn = self.settings.as_ref().is_some_and(|s| s.lowres_turn != 0);
 
             for i in 0..num_tics {
                 if let Some(cmd) = packet.read_full_ticcmd(lowres_turn) {
@@ -456,7 +906,7 @@ impl Client {
             }
 
             self.need_acknowledge = true;
-            self.gamedata_recv_time = Instant::now();
+            self.gamedata_recv_time = self.clock.now();
             self.check_for_missing_tics(seq);
 
             // Send an immediate ACK for the game data
@@ -464,8 +914,52 @@ impl Client {
         }
     }
 
+    /// The server acknowledges every tic before the one it sends, which lets
+    /// us stop resending them and measure how long they took to get there.
+    fn parse_game_data_ack(&mut self, packet: &mut Packet) {
+        if self.state != ClientState::InGame {
+            return;
+        }
+
+        let Some(ackseq) = packet.read_u8() else {
+            return;
+        };
+        let acked = Self::expand_tic_num(self.send_window_start, ackseq as u32);
+
+        if acked <= self.send_window_start {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mut newest_sample = None;
+        for tic in self.send_window_start..acked {
+            let sendobj = &mut self.send_queue[tic as usize % BACKUPTICS];
+            if sendobj.active && sendobj.seq == tic {
+                sendobj.active = false;
+                newest_sample = Some(now.saturating_duration_since(sendobj.time));
+            }
+        }
+
+        // Only the newest tic is sampled, older ones were waiting on it.
+        if let Some(sample) = newest_sample {
+            self.last_latency = self.rtt_estimator.update(sample);
+        }
+
+        debug!(
+            "Server acknowledged tics {}-{}, rtt={}ms",
+            self.send_window_start,
+            acked - 1,
+            self.last_latency
+        );
+        self.send_window_start = acked;
+    }
+
     fn store_received_tic(&mut self, seq: u32, cmd: FullTicCmd) {
-        let index = (seq - self.recv_window_start) as usize;
+        // Tics from before the window were already run.
+        let Some(index) = seq.checked_sub(self.recv_window_start) else {
+            return;
+        };
+        let index = index as usize;
         if index < BACKUPTICS {
             self.recv_window[index].active = true;
             self.recv_window[index].cmd = cmd;
@@ -475,7 +969,8 @@ impl Client {
     }
 
     fn check_for_missing_tics(&mut self, seq: u32) {
-        let resend_end = seq as i32 - self.recv_window_start as i32;
+        // Tics past the receive window could not be stored anyway.
+        let resend_end = (seq as i32 - self.recv_window_start as i32).min(BACKUPTICS as i32);
         if resend_end > 0 {
             let mut resend_start = resend_end - 1;
             while resend_start >= 0 && !self.recv_window[resend_start as usize].active {
@@ -483,8 +978,8 @@ impl Client {
             }
             if resend_start < resend_end - 1 {
                 self.send_resend_request(
-                    self.recv_window_start + resend_start as u32 + 1,
-                    self.recv_window_start + resend_end as u32 - 1,
+                    self.recv_window_start + (resend_start + 1) as u32,
+                    self.recv_window_start + (resend_end - 1) as u32,
                 );
             }
         }
@@ -498,12 +993,24 @@ impl Client {
         }
 
         if let (Some(start), Some(num_tics)) = (packet.read_i32(), packet.read_u8()) {
-            let end = start + num_tics as i32 - 1;
             debug!("Resend request: start={}, num_tics={}", start, num_tics);
+            if num_tics == 0 {
+                warn!("Ignoring resend request for no tics");
+                return;
+            }
 
-            let (resend_start, resend_end) = self.calculate_resend_range(start as u32, end as u32);
+            let end = start.saturating_add(num_tics as i32 - 1);
+            if end < 0 {
+                warn!("Don't have the tics to resend");
+                return;
+            }
 
-            if resend_start <= resend_end {
+            // The send queue only holds the last BACKUPTICS tics.
+            let oldest = self.send_window_start.saturating_sub(BACKUPTICS as u32);
+            let start = (start.max(0) as u32).max(oldest);
+
+            if let Some((resend_start, resend_end)) = self.calculate_resend_range(start, end as u32)
+            {
                 debug!("Resending tics {}-{}", resend_start, resend_end);
                 self.send_tics(resend_start, resend_end);
             } else {
@@ -512,38 +1019,38 @@ impl Client {
         }
     }
 
-    fn calculate_resend_range(&self, start: u32, end: u32) -> (u32, u32) {
-        let mut resend_start = start;
-        let mut resend_end = end;
+    /// Narrows `start..=end` down to the first and last tics still in the
+    /// send queue, if there are any.
+    fn calculate_resend_range(&self, start: u32, end: u32) -> Option<(u32, u32)> {
+        let is_queued = |tic: u32| {
+            let sendobj = &self.send_queue[tic as usize % BACKUPTICS];
+            sendobj.active && sendobj.seq == tic
+        };
+
+        let resend_start = (start..=end).find(|&tic| is_queued(tic))?;
+        let resend_end = (resend_start..=end).rev().find(|&tic| is_queued(tic))?;
+        Some((resend_start, resend_end))
+    }
 
-        while resend_start <= resend_end {
-            let index = resend_start as usize % BACKUPTICS;
-            if self.send_queue[index].active && self.send_queue[index].seq == resend_start {
-                break;
+    fn parse_console_message(&mut self, packet: &mut Packet) {
+        if let Some(text) = packet.read_string() {
+            info!("Message from server:\n{}", text);
+            let message = ConsoleMessage::new(text);
+            if let Some(notice) = &message.notice {
+                debug!("Server notice: {:?}", notice);
             }
-            resend_start += 1;
-        }
 
-        while resend_start <= resend_end {
-            let index = resend_end as usize % BACKUPTICS;
-            if self.send_queue[index].active && self.send_queue[index].seq == resend_end {
-                break;
+            self.emit(ClientEvent::ConsoleMessage(message.clone()));
+            if self.console_messages.len() >= MAX_QUEUED_EVENTS {
+                self.console_messages.pop_front();
             }
-            resend_end -= 1;
-        }
-
-        (resend_start, resend_end)
-    }
-
-    fn parse_console_message(&self, packet: &mut Packet) {
-        if let Some(msg) = packet.read_string() {
-            info!("Message from server:\n{}", msg);
+            self.console_messages.push_back(message);
         }
     }
 
-    fn expand_tic_num(&self, b: u32) -> u32 {
-        let l = self.recv_window_start & 0xff;
-        let h = self.recv_window_start & !0xff;
+    fn expand_tic_num(base: u32, b: u32) -> u32 {
+        let l = base & 0xff;
+        let h = base & !0xff;
         let mut result = h | b;
 
         if l < 0x40 && b > 0xb0 {
@@ -556,20 +1063,28 @@ impl Client {
         result
     }
 
+    // Like Chocolate Doom, the latency compared with the server's is that
+    // of tic `seq`: from sending ours to receiving the server's.
     fn update_clock_sync(&mut self, seq: u32, remote_latency: i32) {
-        let latency = self.send_queue[seq as usize % BACKUPTICS]
-            .time
-            .elapsed()
-            .as_millis() as i32;
+        let sent = &self.send_queue[seq as usize % BACKUPTICS];
+        let was_sent = sent.seq == seq && (sent.active || seq < self.send_window_start);
+        let latency = if was_sent {
+            let elapsed = self.clock.now().saturating_duration_since(sent.time);
+            elapsed.as_millis().min(i32::MAX as u128) as i32
+        } else if seq >= sent.seq {
+            // The server's tic came before we even sent ours.
+            0
+        } else {
+            return;
+        };
         let error = latency - remote_latency;
 
-        let offset_ms = self.pid_controller.update(error);
-
-        self.last_latency = latency;
+        let output = self.pid_controller.update(error);
+        self.offset_ms = output.clamp(-MAX_CLOCK_OFFSET_MS, MAX_CLOCK_OFFSET_MS);
 
         debug!(
-            "Latency {}, remote {}, offset={}ms",
-            latency, remote_latency, offset_ms
+            "Latency {}, remote {}, offset={}ms (unclamped {}ms)",
+            latency, remote_latency, self.offset_ms, output
         );
     }
 
@@ -580,7 +1095,7 @@ impl Client {
         packet.write_u8((end - start + 1) as u8);
         self.send_packet(&packet);
 
-        let now = Instant::now();
+        let now = self.clock.now();
         for i in start..=end {
             let index = (i - self.recv_window_start) as usize;
             if index < BACKUPTICS {
@@ -610,11 +1125,12 @@ impl Client {
         packet.write_u8((start & 0xff) as u8);
         packet.write_u8(((end - start + 1) & 0xff) as u8);
 
-        let lowres_turn = self.settings.as_ref().map_or(false, |s| s.lowres_turn != 0);
+        let lowres_turn = self.settings.as_ref().is_some_and(|s| s.lowres_turn != 0);
 
         for tic in start..=end {
             if let Some(send_obj) = self.send_queue.get(tic as usize % BACKUPTICS) {
-                packet.write_i16(self.last_latency.try_into().unwrap());
+                // A stalled link can take longer than the field holds.
+                packet.write_i16(self.last_latency.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
                 packet.write_ticcmd_diff(&send_obj.cmd, lowres_turn);
             }
         }
@@ -624,23 +1140,26 @@ impl Client {
         debug!("Sent tics from {} to {}", start, end);
     }
 
+    /// Queues the local player's command for tic `maketic` and sends it,
+    /// along with any earlier ones the server has not acknowledged yet.
     pub fn send_ticcmd(&mut self, ticcmd: &TicCmd, maketic: u32) {
         let mut diff = TicDiff::default();
         self.calculate_ticcmd_diff(ticcmd, &mut diff);
+        self.last_ticcmd = *ticcmd;
 
         let sendobj = &mut self.send_queue[maketic as usize % BACKUPTICS];
         sendobj.active = true;
         sendobj.seq = maketic;
-        sendobj.time = Instant::now();
+        sendobj.time = self.clock.now();
         sendobj.cmd = diff;
 
-        let starttic = self.settings.as_ref().map_or(0, |s| {
-            if maketic < s.extratics as u32 {
-                0
-            } else {
-                maketic - s.extratics as u32
-            }
-        });
+        // Tics the server has acknowledged do not need to be sent again.
+        let starttic = self
+            .settings
+            .as_ref()
+            .map_or(0, |s| maketic.saturating_sub(s.extratics as u32))
+            .max(self.send_window_start)
+            .min(maketic);
         let endtic = maketic;
 
         self.send_tics(starttic, endtic);
@@ -690,10 +1209,13 @@ impl Client {
             let window = self.recv_window[0].cmd;
             self.expand_full_ticcmd(&window, window_start, &mut ticcmds);
 
-            self.receive_tic(&ticcmds, &self.recv_window[0].cmd.playeringame);
+            self.received_tics.push_back(ReceivedTic {
+                cmds: ticcmds,
+                ingame: window.playeringame,
+            });
 
             self.recv_window.rotate_left(1);
-            self.recv_window[BACKUPTICS - 1] = ServerRecv::default();
+            self.recv_window[BACKUPTICS - 1] = ServerRecv::new(self.clock.now());
             self.recv_window_start += 1;
 
             debug!("Advanced receive window to {}", self.recv_window_start);
@@ -768,32 +1290,20 @@ impl Client {
         *base = *result;
     }
 
-    fn receive_tic(
-        &self,
-        _ticcmds: &[TicCmd; NET_MAXPLAYERS],
-        playeringame: &[bool; NET_MAXPLAYERS],
-    ) {
-        // TODO: Implement this.
-        debug!(
-            "Received tic data for {} players",
-            playeringame.iter().filter(|&&p| p).count()
-        );
-    }
-
     fn check_resends(&mut self) {
-        let now = Instant::now();
+        let now = self.clock.now();
         let mut resend_start = -1;
         let mut resend_end = -1;
         let maybe_deadlocked = now.duration_since(self.gamedata_recv_time) > Duration::from_secs(1);
 
         for i in 0..BACKUPTICS {
             let recvobj = &mut self.recv_window[i];
-            let need_resend =
-                !recvobj.active && recvobj.resend_time.elapsed() > Duration::from_millis(300);
+            let need_resend = !recvobj.active
+                && now.saturating_duration_since(recvobj.resend_time) > Duration::from_millis(300);
 
             if i == 0
                 && !recvobj.active
-                && recvobj.resend_time.elapsed() > Duration::from_secs(1)
+                && now.saturating_duration_since(recvobj.resend_time) > Duration::from_secs(1)
                 && maybe_deadlocked
             {
                 let _need_resend = true;
@@ -841,43 +1351,89 @@ impl Client {
         }
     }
 
-    fn run_bot(&mut self) {
-        if self.state == ClientState::InGame && self.drone {
-            let maketic = self.recv_window_start + BACKUPTICS as u32;
-            let mut bot_ticcmd = TicCmd::default();
-            self.generate_bot_ticcmd(&mut bot_ticcmd);
-            self.send_ticcmd(&bot_ticcmd, maketic);
-        }
-    }
+    /// Tells the server we are leaving, blocking until it acknowledges or
+    /// the attempt times out.
+    pub fn disconnect(&mut self) {
+        self.begin_disconnect();
 
-    fn generate_bot_ticcmd(&self, ticcmd: &mut TicCmd) {
-        // TODO: Implement more sophisticated bot AI logic
-        ticcmd.forwardmove = 50;
-        ticcmd.sidemove = 0;
-        ticcmd.angleturn = 0;
+        while self.state == ClientState::Disconnecting {
+            self.run();
+            self.clock.sleep(Duration::from_millis(10));
+        }
     }
 
-    pub fn disconnect(&mut self) {
-        if !self.net_client_connected {
+    /// Starts leaving the server. The disconnect is resent by
+    /// [`Client::run`] until the server acknowledges it or
+    /// `DISCONNECT_TIMEOUT` passes.
+    pub(crate) fn begin_disconnect(&mut self) {
+        if !self.net_client_connected || self.state == ClientState::Disconnecting {
             return;
         }
 
         info!("Beginning disconnect");
-        self.state = ClientState::Disconnecting;
-        self.start_time = Instant::now();
+        self.set_state(ClientState::Disconnecting);
+        self.start_time = self.clock.now();
+        // Nothing else is worth sending any more.
+        self.outgoing_reliables.clear();
+        self.pending_start = None;
+        self.send_disconnect();
+    }
 
-        // Send disconnect packet five times
-        for _ in 0..5 {
-            let mut packet = Packet::new();
-            packet.write_u16(PacketType::Disconnect.to_u16());
-            self.send_packet(&packet);
+    /// Launches the game, if we control the lobby. Once the server confirms
+    /// the launch, the game is started with `settings`.
+    pub fn start_game(&mut self, settings: GameSettings) -> Result<(), ClientError> {
+        let is_controller = self
+            .get_wait_data()
+            .is_some_and(|wait_data| wait_data.is_controller != 0);
+        if self.state != ClientState::WaitingLaunch || !is_controller {
+            return Err(ClientError::NotController);
         }
 
-        self.state = ClientState::Disconnected;
-        self.shutdown();
-        info!("Disconnect complete");
+        info!("Launching the game");
+        self.pending_start = Some(settings);
+        self.send_reliable(PacketType::Launch, |_| {});
+        Ok(())
+    }
+
+    /// Takes the oldest tic received from the server that the game has not
+    /// run yet. Our own commands are left out unless we are a drone.
+    pub fn next_received_tic(&mut self) -> Option<ReceivedTic> {
+        self.received_tics.pop_front()
+    }
+
+    /// How many milliseconds the local clock should be shifted by to stay in
+    /// step with the server, as computed by the clock sync controller.
+    pub fn get_offset_ms(&self) -> i32 {
+        self.offset_ms
     }
 
+    /// Takes the oldest event that has not been handled yet.
+    pub fn next_event(&mut self) -> Option<ClientEvent> {
+        self.events.pop_front()
+    }
+
+    /// Returns a channel that gets a copy of every event from now on, for
+    /// watching the client from another task or thread.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<ClientEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// The lobby state, once the server has sent it.
+    pub fn get_wait_data(&self) -> Option<&WaitData> {
+        if !self.net_client_received_wait_data {
+            return None;
+        }
+        Some(&self.net_client_wait_data)
+    }
+
+    /// Who is in the lobby, once the server has said.
+    pub fn get_lobby(&self) -> Option<Lobby> {
+        self.get_wait_data().map(Lobby::from_wait_data)
+    }
+
+    /// The settings of the running game, once it has started.
     pub fn get_settings(&self) -> Option<GameSettings> {
         if self.state != ClientState::InGame {
             return None;
@@ -885,29 +1441,276 @@ impl Client {
         self.settings
     }
 
-    fn send_packet(&self, packet: &Packet) {
+    fn send_packet(&mut self, packet: &Packet) {
         if let Some(server_addr) = self.server_addr {
-            if let Err(e) = self.socket.send_to(&packet.data, server_addr) {
-                warn!("Failed to send packet: {}", e);
+            self.send_packet_to(packet, server_addr);
+        }
+    }
+
+    /// Receives a packet, with IPv4 senders reported by their IPv4 address
+    /// even on a dual-stack socket.
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let Some(socket) = &self.socket else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        let (size, addr) = socket.recv_from(buf)?;
+        Ok((size, SocketAddr::new(addr.ip().to_canonical(), addr.port())))
+    }
+
+    fn send_packet_to(&mut self, packet: &Packet, addr: SocketAddr) {
+        // Dual-stack sockets reach IPv4 hosts through mapped addresses.
+        let addr = match addr {
+            SocketAddr::V4(v4) if self.local_addr.is_ipv6() => {
+                SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
             }
+            _ => addr,
+        };
+        let Some(socket) = &self.socket else {
+            self.outgoing.push((packet.data().to_vec(), addr));
+            return;
+        };
+        if let Err(e) = socket.send_to(packet.data(), addr) {
+            warn!("Failed to send packet to {}: {}", addr, e);
         }
     }
 
-    pub fn connect<A: ToSocketAddrs>(
+    /// The packets to send since the last call, with where they go, when
+    /// someone else owns the socket.
+    pub(crate) fn take_outgoing(&mut self) -> Vec<Datagram> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub(crate) fn get_clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Broadcasts a query to `target` and collects the servers that answer
+    /// within `timeout`, sorted by ping.
+    ///
+    /// `target` is usually the broadcast address on [`DEFAULT_PORT`], but any
+    /// unicast address works too, which is how a single server is queried.
+    pub fn search_lan(
         &mut self,
-        addr: A,
+        target: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Vec<ServerInfo>, ClientError> {
+        info!("Searching for servers via {}", target);
+        let Some(socket) = &self.socket else {
+            return Err(
+                io::Error::new(io::ErrorKind::Unsupported, "no socket to broadcast on").into(),
+            );
+        };
+        socket.set_broadcast(true)?;
+        let servers = self.query_servers(&[target], timeout);
+        if let Some(socket) = &self.socket {
+            socket.set_broadcast(false)?;
+        }
+
+        Ok(servers)
+    }
+
+    /// Queries every address in `targets` and collects the servers that
+    /// answer within `timeout`, sorted by ping.
+    pub fn query_servers(&mut self, targets: &[SocketAddr], timeout: Duration) -> Vec<ServerInfo> {
+        let mut servers: Vec<ServerInfo> = Vec::new();
+        let mut attempts = 0;
+        let mut query_time = self.clock.now();
+        let start = self.clock.now();
+
+        while self.clock.elapsed(start) < timeout {
+            // Broadcast responses come from other addresses than the target,
+            // so this only ends early when querying known servers.
+            let all_answered = targets
+                .iter()
+                .all(|target| servers.iter().any(|server| server.addr == *target));
+            if attempts > 0 && all_answered {
+                break;
+            }
+
+            if attempts < QUERY_MAX_ATTEMPTS
+                && (attempts == 0 || self.clock.elapsed(query_time) > QUERY_SEND_INTERVAL)
+            {
+                let mut packet = Packet::new();
+                packet.write_u16(PacketType::Query.to_u16());
+                for &target in targets {
+                    if !servers.iter().any(|server| server.addr == target) {
+                        self.send_packet_to(&packet, target);
+                    }
+                }
+                query_time = self.clock.now();
+                attempts += 1;
+                debug!(
+                    "Sent query round {} to {} target(s)",
+                    attempts,
+                    targets.len()
+                );
+            }
+
+            let mut buf = [0u8; 4096];
+            while let Ok((size, addr)) = self.recv_from(&mut buf) {
+                let mut packet = Packet::from_data(&buf[..size]);
+
+                if packet.read_u16().and_then(PacketType::from_u16)
+                    != Some(PacketType::QueryResponse)
+                {
+                    debug!("Ignoring non-query packet from {:?}", addr);
+                    continue;
+                }
+
+                let Some(query) = packet.read_query_data() else {
+                    warn!("Malformed query response from {:?}", addr);
+                    continue;
+                };
+
+                if servers.iter().any(|server| server.addr == addr) {
+                    continue;
+                }
+
+                let ping = self.clock.elapsed(query_time);
+                debug!("Query response from {:?} ({:?}): {:?}", addr, ping, query);
+                servers.push(ServerInfo { addr, query, ping });
+            }
+
+            self.clock.sleep(Duration::from_millis(10));
+        }
+
+        servers.sort_by_key(|server| server.ping);
+        info!("Found {} server(s)", servers.len());
+
+        servers
+    }
+
+    /// Fetches the list of registered servers from a master server.
+    ///
+    /// The list may be split across several packets, so addresses keep being
+    /// collected until the master has been quiet for a while.
+    pub fn query_master(
+        &mut self,
+        master: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Vec<SocketAddr>, ClientError> {
+        info!("Querying master server at {}", master);
+
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        let mut attempts = 0;
+        let mut query_time = self.clock.now();
+        let mut last_response: Option<Instant> = None;
+        let start = self.clock.now();
+
+        while self.clock.elapsed(start) < timeout {
+            match last_response {
+                Some(time) if self.clock.elapsed(time) > QUERY_SEND_INTERVAL => break,
+                Some(_) => {}
+                None if attempts == 0 || self.clock.elapsed(query_time) > QUERY_SEND_INTERVAL => {
+                    if attempts >= QUERY_MAX_ATTEMPTS {
+                        break;
+                    }
+
+                    let mut packet = Packet::new();
+                    packet.write_u16(MasterPacketType::Query.to_u16());
+                    self.send_packet_to(&packet, master);
+                    query_time = self.clock.now();
+                    attempts += 1;
+                }
+                None => {}
+            }
+
+            let mut buf = [0u8; 4096];
+            while let Ok((size, addr)) = self.recv_from(&mut buf) {
+                if addr != master {
+                    debug!("Ignoring packet from {:?} while querying master", addr);
+                    continue;
+                }
+
+                let mut packet = Packet::from_data(&buf[..size]);
+
+                if packet.read_u16().and_then(MasterPacketType::from_u16)
+                    != Some(MasterPacketType::QueryResponse)
+                {
+                    debug!("Ignoring unexpected master packet: {:x?}", packet.data());
+                    continue;
+                }
+
+                while let Some(entry) = packet.read_safe_string() {
+                    match resolve_address(&entry, self.local_addr) {
+                        Ok(addr) if !addrs.contains(&addr) => addrs.push(addr),
+                        Ok(_) => {}
+                        Err(e) => warn!("Master listed unresolvable address {}: {}", entry, e),
+                    }
+                }
+
+                last_response = Some(self.clock.now());
+            }
+
+            self.clock.sleep(Duration::from_millis(10));
+        }
+
+        if last_response.is_none() {
+            return Err(ClientError::NoResponse {
+                addr: master,
+                retries: attempts,
+            });
+        }
+
+        info!("Master server listed {} server(s)", addrs.len());
+        Ok(addrs)
+    }
+
+    /// Sets the master server used to coordinate NAT hole punching when a
+    /// server does not answer our SYN.
+    pub fn set_master_server(&mut self, addr: SocketAddr) {
+        self.master_addr = Some(addr);
+    }
+
+    /// Asks the master server to have the server send us a packet, which
+    /// opens its NAT for our SYNs, and punches our own NAT towards the server.
+    fn request_hole_punch(&mut self) {
+        let Some(server_addr) = self.server_addr else {
+            return;
+        };
+
+        let mut punch = Packet::new();
+        punch.write_u16(PacketType::NatHolePunch.to_u16());
+        self.send_packet_to(&punch, server_addr);
+
+        if let Some(master_addr) = self.master_addr {
+            info!(
+                "No response from {}, requesting NAT hole punch from {}",
+                server_addr, master_addr
+            );
+
+            let mut packet = Packet::new();
+            packet.write_u16(MasterPacketType::NatHolePunch.to_u16());
+            packet.write_string(&server_addr.to_string());
+            self.send_packet_to(&packet, master_addr);
+        }
+    }
+
+    /// Joins the server at `addr`, blocking until it lets us into its lobby.
+    pub fn connect(
+        &mut self,
+        addr: SocketAddr,
         connect_data: ConnectData,
-    ) -> Result<(), String> {
-        let addr = addr
-            .to_socket_addrs()
-            .map_err(|e| format!("Failed to resolve address: {}", e))?
-            .next()
-            .ok_or_else(|| "No valid address found".to_string())?;
+    ) -> Result<(), ClientError> {
+        self.begin_connect(addr, connect_data);
+
+        loop {
+            if let Some(result) = self.poll_connect() {
+                return result;
+            }
+
+            self.run();
+            self.clock.sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Sets up a connection attempt, to be driven by [`Client::poll_connect`].
+    pub(crate) fn begin_connect(&mut self, addr: SocketAddr, connect_data: ConnectData) {
         info!("Attempting to connect to server at {:?}", addr);
         self.server_addr = Some(addr);
 
-        self.state = ClientState::Connecting;
-        self.reject_reason = Some("Unknown reason".to_string());
+        self.set_state(ClientState::Connecting);
+        self.connect_error = None;
 
         self.net_local_wad_sha1sum
             .copy_from_slice(&connect_data.wad_sha1sum);
@@ -915,134 +1718,174 @@ impl Client {
             .copy_from_slice(&connect_data.deh_sha1sum);
         self.net_local_is_freedoom = connect_data.is_freedoom != 0;
 
-        self.gamemode = connect_data.gamemode;
+        self.gamemode = GameMode::from_i32(connect_data.gamemode).unwrap_or(GameMode::Indetermined);
         self.gamemission = connect_data.gamemission;
         self.lowres_turn = connect_data.lowres_turn;
         self.max_players = connect_data.max_players;
         self.is_freedoom = connect_data.is_freedoom;
         self.player_class = connect_data.player_class;
+        self.connect_data = connect_data;
 
         self.net_client_connected = false;
         self.net_client_received_wait_data = false;
-
-        self.start_time = Instant::now();
-        self.last_send_time = Instant::now() - KEEPALIVE_PERIOD;
+        self.reliable_recv_seq = 0;
+        self.reliable_send_seq = 0;
+        self.outgoing_reliables.clear();
+        self.pending_start = None;
+        self.chat_queue = ChatQueue::default();
+
+        self.start_time = self.clock.now();
+        self.last_send_time = self.start_time - KEEPALIVE_PERIOD;
         self.num_retries = 0;
+        self.last_syn_time = None;
+        self.hole_punch_requested = false;
+    }
 
-        while self.state == ClientState::Connecting {
-            if self.start_time.elapsed() > CONNECTION_TIMEOUT {
-                return Err(format!(
-                    "Connection timed out after {} seconds",
-                    CONNECTION_TIMEOUT.as_secs()
-                ));
-            }
+    /// Sends whatever the connection attempt needs next. Returns how it went
+    /// once that is known.
+    pub(crate) fn poll_connect(&mut self) -> Option<Result<(), ClientError>> {
+        let addr = self.server_addr?;
 
-            if self.num_retries >= MAX_RETRIES {
-                return Err(format!("Connection failed after {} retries", MAX_RETRIES));
+        if self.state != ClientState::Connecting {
+            if self.net_client_connected {
+                info!("Successfully connected");
+                return Some(Ok(()));
             }
 
-            info!("Sending SYN packet, attempt {}", self.num_retries + 1);
-            self.send_syn(&connect_data);
-
-            self.num_retries += 1;
-
-            for _ in 0..10 {
-                self.run();
-                let reject_reason = self.reject_reason.clone();
+            return Some(Err(self.connect_error.take().unwrap_or_else(|| {
+                ClientError::Rejected("Unknown reason".to_string())
+            })));
+        }
 
-                if self.state == ClientState::Connected {
-                    break;
-                } else if let Some(reject_reason) = reject_reason {
-                    self.disconnect();
-                    return Err(format!("Connection rejected: {}", reject_reason));
-                }
+        if self.clock.elapsed(self.start_time) > self.config.connection_timeout {
+            self.set_state(ClientState::Disconnected);
+            return Some(Err(ClientError::Timeout {
+                addr,
+                timeout: self.config.connection_timeout,
+            }));
+        }
 
-                thread::sleep(Duration::from_millis(200));
+        if self
+            .last_syn_time
+            .is_none_or(|time| self.clock.elapsed(time) > SYN_SEND_INTERVAL)
+        {
+            if self.num_retries >= self.config.max_retries {
+                self.set_state(ClientState::Disconnected);
+                return Some(Err(ClientError::NoResponse {
+                    addr,
+                    retries: self.config.max_retries,
+                }));
             }
 
-            if self.state == ClientState::Connected {
-                info!("Successfully connected");
-                self.reject_reason = None;
-                self.state = ClientState::WaitingLaunch;
-                self.drone = connect_data.drone != 0;
-                self.net_client_connected = true;
-                return Ok(());
-            }
+            info!("Sending SYN packet, attempt {}", self.num_retries + 1);
+            self.send_syn();
+            self.num_retries += 1;
+            self.last_syn_time = Some(self.clock.now());
+        }
 
-            info!(
-                "Connection attempt {} failed, retrying...",
-                self.num_retries
-            );
-            thread::sleep(Duration::from_secs(2));
+        if !self.hole_punch_requested && self.clock.elapsed(self.start_time) > HOLE_PUNCH_DELAY {
+            self.request_hole_punch();
+            self.hole_punch_requested = true;
         }
 
-        Err(format!(
-            "Connection failed. Reason: {:?}",
-            self.reject_reason
-        ))
+        None
     }
 
-    fn send_syn(&mut self, connect_data: &ConnectData) {
+    fn send_syn(&mut self) {
         let mut packet = Packet::new();
-
-        // 1. Packet Type (SYN)
         packet.write_u16(PacketType::Syn.to_u16());
+        packet.write_u32(NET_MAGIC_NUMBER);
+        packet.write_string(&self.config.game_description);
+        if let Err(e) = packet.write_protocol(Protocol::ChocolateDoom0) {
+            error!("Failed to write SYN packet: {}", e);
+            return;
+        }
+        packet.write_connect_data(&self.connect_data);
+        packet.write_string(&self.config.player_name);
 
-        // 2. Random Challenge
-        packet.write_u32(rand::random());
-
-        // 3. Game Description
-        packet.write_string("Chocolate Doom 3.0.1");
-
-        // 4. Number of Protocols
-        packet.write_u8(1);
+        self.send_packet(&packet);
+        info!("SYN sent to server: {} bytes", packet.data().len());
+    }
 
-        // 5. Protocol Identifier
-        packet.write_string("CHOCOLATE_DOOM_0");
+    /// Takes the commands of a tic the game ran, putting together the chat
+    /// they carry. Returns the lines of chat the tic finished.
+    pub fn run_tic(
+        &mut self,
+        cmds: &[TicCmd; NET_MAXPLAYERS],
+        ingame: &[bool; NET_MAXPLAYERS],
+    ) -> Vec<ChatMessage> {
+        let mut messages = Vec::new();
+        for (player, cmd) in cmds.iter().enumerate() {
+            if !ingame[player] {
+                continue;
+            }
 
-        // 6. Calculate Data Length
-        let player_name_len = self.player_name.len() + 1; // +1 for null terminator
-        let data_length = 6 + 20 + 20 + 1 + player_name_len; // Should be around 56 bytes
+            if let Some((to, text)) = self.chat.receive(player, cmd.chatchar) {
+                let name = self
+                    .get_lobby()
+                    .and_then(|lobby| lobby.players.get(player).map(|p| p.name.clone()))
+                    .unwrap_or_else(|| format!("Player {}", player + 1));
+                info!("{}: {}", name, text);
+                let message = ChatMessage {
+                    from: player,
+                    name,
+                    to,
+                    text,
+                };
+                self.emit(ClientEvent::Chat(message.clone()));
+                messages.push(message);
+            }
+        }
+        messages
+    }
 
-        // Write Data Length in Little-Endian
-        packet.write_u32(data_length as u32);
+    /// Says `text` in the game chat. It goes out one character per tic with
+    /// our commands, so drones cannot chat.
+    pub fn send_chat(&mut self, to: ChatDestination, text: &str) {
+        if self.drone {
+            warn!("Drones cannot chat, dropping {:?}", text);
+            return;
+        }
+        self.chat_queue.push(to, text);
+    }
 
-        // 7. Connect Data
-        packet.write_u8(connect_data.gamemode as u8);
-        packet.write_u8(connect_data.gamemission as u8);
-        packet.write_u8(connect_data.lowres_turn as u8);
-        packet.write_u8(connect_data.drone as u8);
-        packet.write_u8(connect_data.max_players as u8);
-        packet.write_u8(connect_data.is_freedoom as u8);
-        packet.write_blob(&connect_data.wad_sha1sum);
-        packet.write_blob(&connect_data.deh_sha1sum);
-        packet.write_u8(connect_data.player_class as u8);
-        packet.write_string(&self.player_name);
+    /// Takes the console messages the game loop has not seen yet.
+    pub(crate) fn take_console_messages(&mut self) -> impl Iterator<Item = ConsoleMessage> + '_ {
+        self.console_messages.drain(..)
+    }
 
-        // Send the packet
-        self.send_packet(&packet);
-        info!("SYN sent to server: {} bytes", packet.data.len());
+    /// The next character of chat to send, for the game loop.
+    pub(crate) fn next_chat_char(&mut self) -> Option<u8> {
+        self.chat_queue.next_char()
     }
 
-    pub fn build_ticcmd(&mut self, cmd: &mut TicCmd, _maketic: u32) {
-        // TODO: Implement actual ticcmd building logic
-        *cmd = TicCmd::default();
+    /// The address our socket is bound to.
+    pub fn get_local_addr(&self) -> SocketAddr {
+        self.local_addr
     }
 
-    pub fn run_tic(&mut self, _cmds: &[TicCmd; NET_MAXPLAYERS], _ingame: &[bool; NET_MAXPLAYERS]) {
-        // TODO: Implement actual tic running logic
-        // Commented out for now to avoid unused variable warnings
-        // for (i, (cmd, &in_game)) in _cmds.iter().zip(_ingame.iter()).enumerate() {
-        //     if in_game {
-        //         debug!("Player {}: {:?}", i, cmd);
-        //     }
-        // }
+    /// The name we joined with.
+    pub fn get_name(&self) -> &str {
+        &self.config.player_name
     }
 
+    /// Whether the server let us in as a spectator.
     pub fn is_drone(&self) -> bool {
         self.drone
     }
 
+    /// The game release we joined with.
+    pub fn get_gamemode(&self) -> GameMode {
+        self.gamemode
+    }
+
+    /// Where the connection is at, from connecting to disconnected.
+    pub fn get_state(&self) -> ClientState {
+        self.state
+    }
+
+    /// Whether the server has let us in and the connection has not ended
+    /// since, whether in the lobby or in game.
     pub fn is_connected(&self) -> bool {
         self.net_client_connected
     }
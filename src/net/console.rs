@@ -0,0 +1,57 @@
+use std::time::SystemTime;
+
+/// Text the server wants shown on the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleMessage {
+    /// When the message arrived.
+    pub time: SystemTime,
+    /// The message as sent.
+    pub text: String,
+    /// What the message means, if it is a notice we know.
+    pub notice: Option<ServerNotice>,
+}
+
+impl ConsoleMessage {
+    /// A message arriving now, recognizing any notice in it.
+    pub fn new(text: String) -> Self {
+        ConsoleMessage {
+            time: SystemTime::now(),
+            notice: ServerNotice::parse(&text),
+            text,
+        }
+    }
+}
+
+/// The console messages servers send about what goes on around the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerNotice {
+    /// Someone left, or stopped answering.
+    PlayerLeft {
+        /// The name of whoever left.
+        name: String,
+    },
+}
+
+impl ServerNotice {
+    /// Recognizes the notices Chocolate Doom servers send,
+    /// `Player 2 (Alice) left the game.` and
+    /// `Client 'Bob' timed out and disconnected`. Anything else, including
+    /// chat that merely looks like them, is not a notice.
+    pub fn parse(text: &str) -> Option<ServerNotice> {
+        let text = text.trim_end();
+        let name = match text.strip_prefix("Player ") {
+            Some(rest) => {
+                let (number, rest) = rest.split_once(" (")?;
+                number.parse::<u32>().ok()?;
+                rest.strip_suffix(") left the game.")?
+            }
+            None => text
+                .strip_prefix("Client '")?
+                .strip_suffix("' timed out and disconnected")?,
+        };
+
+        Some(ServerNotice::PlayerLeft {
+            name: name.to_string(),
+        })
+    }
+}
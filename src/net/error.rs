@@ -0,0 +1,77 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Why talking to a server or the master server failed.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The address could not be turned into one we can reach.
+    #[error("Failed to resolve {address}: {source}")]
+    Resolve {
+        /// The address as given.
+        address: String,
+        /// Why it failed.
+        #[source]
+        source: io::Error,
+    },
+
+    /// The server kept quiet through every connection request.
+    #[error("No response from {addr} after {retries} retries")]
+    NoResponse {
+        /// The server's address.
+        addr: SocketAddr,
+        /// How many times the request was resent.
+        retries: u32,
+    },
+
+    /// We were not let in within the connection timeout, whether the server
+    /// answered at all or not.
+    #[error("Connection to {addr} timed out after {} seconds", .timeout.as_secs())]
+    Timeout {
+        /// The server's address.
+        addr: SocketAddr,
+        /// How long we waited.
+        timeout: Duration,
+    },
+
+    /// The server turned us away, with the reason it gave.
+    #[error("Connection rejected: {0}")]
+    Rejected(String),
+
+    /// The server speaks none of the protocols we offered.
+    #[error("No common protocol with the server")]
+    Protocol,
+
+    /// The lobby data shows another WAD than the one asked for. Raised by
+    /// [`ServerFilter::check_wait_data`](super::ServerFilter::check_wait_data)
+    /// for callers that only want servers running the same files. The
+    /// client itself stays connected, like Chocolate Doom, and reports the
+    /// difference as
+    /// [`ClientEvent::Sha1Mismatch`](super::ClientEvent::Sha1Mismatch).
+    #[error("Server is running a different WAD")]
+    WadMismatch,
+
+    /// Like `WadMismatch`, for the DEH.
+    #[error("Server is running a different DEH")]
+    DehMismatch,
+
+    /// Only the lobby controller can start the game, and only from the lobby.
+    #[error("Not the controller of a lobby")]
+    NotController,
+
+    /// The socket failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl ClientError {
+    /// Whether the server may still answer if asked again.
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            ClientError::NoResponse { .. } | ClientError::Timeout { .. }
+        )
+    }
+}
@@ -0,0 +1,89 @@
+use super::{WaitData, NET_MAXPLAYERS};
+
+/// Someone in the lobby, as listed by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyPlayer {
+    /// Where the player is in the lobby list, which is also the player
+    /// number the game will start with.
+    pub slot: usize,
+    /// The name the player joined with.
+    pub name: String,
+    /// Where the player joined from.
+    pub address: String,
+}
+
+/// The lobby as the server last described it.
+///
+/// Only players are listed; drones are just counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lobby {
+    /// The players, in slot order.
+    pub players: Vec<LobbyPlayer>,
+    /// How many drones are watching.
+    pub num_drones: usize,
+    /// How many players are ready to start.
+    pub ready_players: usize,
+    /// Most players the game can have.
+    pub max_players: usize,
+    /// Whether we get to launch the game.
+    pub is_controller: bool,
+    /// Our slot, if we are a player rather than a drone.
+    pub local_slot: Option<usize>,
+}
+
+impl Lobby {
+    /// The lobby described by waiting data from the server.
+    pub fn from_wait_data(wait_data: &WaitData) -> Self {
+        // Launch packets set the player count without the checks lobby data
+        // goes through, so it may be more than there are names for.
+        let num_players = (wait_data.num_players.max(0) as usize).min(NET_MAXPLAYERS);
+        let players = (0..num_players)
+            .map(|slot| LobbyPlayer {
+                slot,
+                name: decode(&wait_data.player_names[slot]),
+                address: decode(&wait_data.player_addrs[slot]),
+            })
+            .collect();
+
+        Lobby {
+            players,
+            num_drones: wait_data.num_drones.max(0) as usize,
+            ready_players: wait_data.ready_players.max(0) as usize,
+            max_players: wait_data.max_players.max(0) as usize,
+            is_controller: wait_data.is_controller != 0,
+            local_slot: usize::try_from(wait_data.consoleplayer).ok(),
+        }
+    }
+
+    /// The player with exactly this name, if there is one.
+    pub fn get_player(&self, name: &str) -> Option<&LobbyPlayer> {
+        self.players.iter().find(|player| player.name == name)
+    }
+
+    /// Whether a player with exactly this name is in the lobby.
+    pub fn has_player(&self, name: &str) -> bool {
+        self.get_player(name).is_some()
+    }
+
+    /// Us, unless we are a drone.
+    pub fn get_local_player(&self) -> Option<&LobbyPlayer> {
+        self.players.get(self.local_slot?)
+    }
+
+    /// Everyone but us.
+    pub fn others(&self) -> impl Iterator<Item = &LobbyPlayer> {
+        self.players
+            .iter()
+            .filter(move |player| Some(player.slot) != self.local_slot)
+    }
+
+    /// Whether there is any player besides us.
+    pub fn is_alone(&self) -> bool {
+        self.others().next().is_none()
+    }
+}
+
+// Names and addresses are NUL padded.
+fn decode(chars: &[char]) -> String {
+    chars.iter().take_while(|&&c| c != '\0').collect()
+}
@@ -1,100 +1,488 @@
 use serde::{Deserialize, Serialize};
-use std::time::Instant;
+use socket2::{Domain, Socket, Type};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
 
+use crate::chat::ChatMessage;
+
+/// The port Chocolate Doom servers listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 2342;
+/// The master server listens on its own port, not the game one.
+pub const DEFAULT_MASTER_PORT: u16 = 27020;
+/// The master server Chocolate Doom servers register with.
+pub const DEFAULT_MASTER_SERVER: &str = "master.chocolate-doom.org:27020";
+/// Starts every SYN, to tell Chocolate Doom clients apart.
+pub const NET_MAGIC_NUMBER: u32 = 1454104972;
+/// Set in the type of packets that must be acknowledged.
+pub const NET_RELIABLE_PACKET: u16 = 1 << 15;
+/// Most players a game can have.
 pub const NET_MAXPLAYERS: usize = 8;
+/// Longest player name or address in lobby data, NUL included.
 pub const MAXPLAYERNAME: usize = 30;
+/// How many tics are kept around for resending and reordering.
 pub const BACKUPTICS: usize = 128;
 
+/// Set in a [`TicDiff`] when `forwardmove` is sent.
 pub const NET_TICDIFF_FORWARD: u32 = 1 << 0;
+/// Set in a [`TicDiff`] when `sidemove` is sent.
 pub const NET_TICDIFF_SIDE: u32 = 1 << 1;
+/// Set in a [`TicDiff`] when `angleturn` is sent.
 pub const NET_TICDIFF_TURN: u32 = 1 << 2;
+/// Set in a [`TicDiff`] when `buttons` is sent.
 pub const NET_TICDIFF_BUTTONS: u32 = 1 << 3;
+/// Set in a [`TicDiff`] when `consistancy` is sent.
 pub const NET_TICDIFF_CONSISTANCY: u32 = 1 << 4;
+/// Set in a [`TicDiff`] when `chatchar` is sent.
 pub const NET_TICDIFF_CHATCHAR: u32 = 1 << 5;
+/// Set in a [`TicDiff`] when Heretic and Hexen's fields are sent.
 pub const NET_TICDIFF_RAVEN: u32 = 1 << 6;
+/// Set in a [`TicDiff`] when Strife's fields are sent.
 pub const NET_TICDIFF_STRIFE: u32 = 1 << 7;
 
+// TicCmd buttons.
+/// Fires the weapon.
+pub const BT_ATTACK: u8 = 1;
+/// Opens doors and flips switches.
+pub const BT_USE: u8 = 2;
+/// Makes the other buttons the `BTS_*` ones.
+pub const BT_SPECIAL: u8 = 128;
+/// Switches to the weapon slot in [`BT_WEAPONMASK`].
+pub const BT_CHANGE: u8 = 4;
+/// The weapon slot to switch to, counted from 0.
+pub const BT_WEAPONMASK: u8 = 8 + 16 + 32;
+/// Where the weapon slot starts in the buttons.
+pub const BT_WEAPONSHIFT: u8 = 3;
+
+// TicCmd buttons when BT_SPECIAL is set.
+/// Pauses or unpauses the game.
+pub const BTS_PAUSE: u8 = 1;
+/// Saves the game in the slot in [`BTS_SAVEMASK`].
+pub const BTS_SAVEGAME: u8 = 2;
+/// The slot to save the game in.
+pub const BTS_SAVEMASK: u8 = 4 + 8 + 16;
+/// Where the save slot starts in the buttons.
+pub const BTS_SAVESHIFT: u8 = 2;
+
+/// The client driven from a tokio runtime.
+pub mod async_client;
+/// The blocking client and its configuration.
 pub mod client;
+/// Messages the server shows on the console.
+pub mod console;
+/// Errors returned by the client API.
+pub mod error;
+/// The lobby as the server describes it.
+pub mod lobby;
+/// Reading and writing packet fields.
 pub mod packet;
 
+pub use self::console::{ConsoleMessage, ServerNotice};
+pub use self::error::ClientError;
+pub use self::lobby::{Lobby, LobbyPlayer};
+
+/// Resolves `host[:port]`, using [`DEFAULT_PORT`] when no port is given.
+/// IPv6 addresses are written in brackets, with or without a port.
+///
+/// Hosts can have addresses of both families, so the first one a socket
+/// bound on `local` can reach is picked: any for a dual-stack socket, as
+/// bound by [`bind_socket`], or else one of its own family.
+pub fn resolve_address(address: &str, local: SocketAddr) -> Result<SocketAddr, ClientError> {
+    resolve_address_with_port(address, DEFAULT_PORT, local)
+}
+
+/// Like [`resolve_address`], using `default_port` when no port is given,
+/// such as [`DEFAULT_MASTER_PORT`] for master servers.
+pub fn resolve_address_with_port(
+    address: &str,
+    default_port: u16,
+    local: SocketAddr,
+) -> Result<SocketAddr, ClientError> {
+    let resolve_error = |source| ClientError::Resolve {
+        address: address.to_string(),
+        source,
+    };
+
+    let addrs = match address.to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(_) => {
+            let host = address
+                .strip_prefix('[')
+                .and_then(|host| host.strip_suffix(']'))
+                .unwrap_or(address);
+            (host, default_port)
+                .to_socket_addrs()
+                .map_err(resolve_error)?
+        }
+    };
+
+    let dual_stack = local.is_ipv6() && local.ip().is_unspecified();
+    let mut addrs = addrs.filter(|addr| dual_stack || addr.is_ipv6() == local.is_ipv6());
+    addrs.next().ok_or_else(|| {
+        resolve_error(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses reachable from {}", local),
+        ))
+    })
+}
+
+/// Binds a UDP socket on `addr`, or on any free port of every interface when
+/// not given.
+///
+/// The IPv6 wildcard address is bound dual-stack so that IPv4 servers stay
+/// reachable through it. Without IPv6 support, the IPv4 wildcard is used.
+pub fn bind_socket(addr: Option<SocketAddr>) -> io::Result<UdpSocket> {
+    let Some(addr) = addr else {
+        return bind_socket(Some((Ipv6Addr::UNSPECIFIED, 0).into()))
+            .or_else(|_| bind_socket(Some((Ipv4Addr::UNSPECIFIED, 0).into())));
+    };
+
+    let socket = Socket::new(
+        Domain::for_address(addr),
+        Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.bind(&addr.into())?;
+
+    Ok(socket.into())
+}
+
+/// The input of one player for one tic.
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TicCmd {
+    /// Speed forwards, negative for backwards.
     pub forwardmove: i8,
+    /// Speed to the right, negative for the left.
     pub sidemove: i8,
+    /// Turn to the left, negative for the right, in 65536ths of a circle.
     pub angleturn: i16,
+    /// The next character of a chat message, or 0 for none.
     pub chatchar: u8,
+    /// `BT_*` flags, or `BTS_*` ones with [`BT_SPECIAL`].
     pub buttons: u8,
+    /// Checked against the player's position to catch games out of sync.
     pub consistancy: u8,
+    /// Strife's extra buttons.
     pub buttons2: u8,
+    /// Strife's inventory item to use.
     pub inventory: i32,
+    /// Heretic and Hexen's looking and flying up or down.
     pub lookfly: u8,
+    /// Heretic and Hexen's artifact to use.
     pub arti: u8,
 }
 
+/// What the client tells the server about its game when joining. Build it
+/// with [`ConnectData::builder`].
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct ConnectData {
+    /// The [`GameMode`] we run.
     pub gamemode: i32,
+    /// The [`GameMission`] we run.
     pub gamemission: i32,
+    /// Whether we turn in steps of 256.
     pub lowres_turn: i32,
+    /// Whether we only watch, without a player.
     pub drone: i32,
+    /// Most players our game supports.
     pub max_players: i32,
+    /// Whether our IWAD is Freedoom.
     pub is_freedoom: i32,
+    /// SHA1 of our WAD files.
     pub wad_sha1sum: [u8; 20],
+    /// SHA1 of our DeHackEd patches.
     pub deh_sha1sum: [u8; 20],
+    /// The class we play as, in Hexen.
     pub player_class: i32,
 }
 
+impl ConnectData {
+    /// Starts from a drone running Doom II with the given WAD.
+    pub fn builder(wad_sha1sum: [u8; 20]) -> ConnectDataBuilder {
+        ConnectDataBuilder {
+            data: ConnectData {
+                gamemode: GameMode::Commercial as i32,
+                gamemission: GameMission::Doom as i32,
+                lowres_turn: 0,
+                drone: 1,
+                max_players: NET_MAXPLAYERS as i32,
+                is_freedoom: 0,
+                wad_sha1sum,
+                deh_sha1sum: [0; 20],
+                player_class: 0,
+            },
+        }
+    }
+}
+
+/// Builds [`ConnectData`] with the game enums instead of raw integers.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectDataBuilder {
+    data: ConnectData,
+}
+
+impl ConnectDataBuilder {
+    /// The game release we run, which servers only accept if it is theirs.
+    pub fn gamemode(mut self, gamemode: GameMode) -> Self {
+        self.data.gamemode = gamemode as i32;
+        self
+    }
+
+    /// The game we run, Doom, Doom II or one of the Final Doom ones.
+    pub fn gamemission(mut self, gamemission: GameMission) -> Self {
+        self.data.gamemission = gamemission as i32;
+        self
+    }
+
+    /// Asks the server for turning in steps of 256 instead of 1.
+    pub fn lowres_turn(mut self, lowres_turn: bool) -> Self {
+        self.data.lowres_turn = lowres_turn as i32;
+        self
+    }
+
+    /// Joins as a spectator instead of taking a player slot.
+    pub fn drone(mut self, drone: bool) -> Self {
+        self.data.drone = drone as i32;
+        self
+    }
+
+    /// The most players the client supports in a game.
+    pub fn max_players(mut self, max_players: i32) -> Self {
+        self.data.max_players = max_players;
+        self
+    }
+
+    /// Whether our IWAD is Freedoom's rather than id's.
+    pub fn freedoom(mut self, is_freedoom: bool) -> Self {
+        self.data.is_freedoom = is_freedoom as i32;
+        self
+    }
+
+    /// The SHA1 of the DeHackEd patches we load, all zeros for none.
+    pub fn deh_sha1sum(mut self, deh_sha1sum: [u8; 20]) -> Self {
+        self.data.deh_sha1sum = deh_sha1sum;
+        self
+    }
+
+    /// The player class, for games that have them.
+    pub fn player_class(mut self, player_class: i32) -> Self {
+        self.data.player_class = player_class;
+        self
+    }
+
+    /// Finishes the connect data.
+    pub fn build(self) -> ConnectData {
+        self.data
+    }
+}
+
+/// The settings the server started the game with.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
 pub struct GameSettings {
+    /// How many tics each command runs for.
     pub ticdup: i32,
+    /// How many older tics are sent along with each new one.
     pub extratics: i32,
+    /// 0 for cooperative, or else the deathmatch rules played.
     pub deathmatch: i32,
+    /// The episode started on.
     pub episode: i32,
+    /// Whether there are no monsters.
     pub nomonsters: i32,
+    /// Whether monsters are fast.
     pub fast_monsters: i32,
+    /// Whether killed monsters come back.
     pub respawn_monsters: i32,
+    /// The map started on.
     pub map: i32,
+    /// The [`Skill`] level.
     pub skill: i32,
+    /// The [`GameVersion`] emulated.
     pub gameversion: i32,
+    /// Whether every player turns in steps of 256.
     pub lowres_turn: i32,
+    /// Whether clocks are synced with the newer, non-lockstep method.
     pub new_sync: i32,
+    /// Minutes before each level ends, or 0 for no limit.
     pub timelimit: u32,
+    /// The save slot loaded, or -1 for none.
     pub loadgame: i32,
+    /// Whether items and monsters are randomized, in Hexen.
     pub random: i32,
+    /// How many players are in the game.
     pub num_players: i32,
+    /// Our player number, or -1 for drones.
     pub consoleplayer: i32,
+    /// The class of each player, in Hexen.
     pub player_classes: [i32; NET_MAXPLAYERS],
 }
 
+impl GameSettings {
+    /// Starts from a cooperative game of the first map on medium skill, as
+    /// Chocolate Doom does without any options.
+    pub fn builder() -> GameSettingsBuilder {
+        GameSettingsBuilder {
+            settings: GameSettings {
+                ticdup: 1,
+                episode: 1,
+                map: 1,
+                skill: Skill::Medium as i32,
+                gameversion: GameVersion::Doom1_9 as i32,
+                new_sync: 1,
+                loadgame: -1,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Builds the [`GameSettings`] a lobby controller starts a game with. The
+/// players are filled in by the server.
+#[derive(Debug, Clone, Copy)]
+pub struct GameSettingsBuilder {
+    settings: GameSettings,
+}
+
+impl GameSettingsBuilder {
+    /// The episode to start on, for games that have episodes.
+    pub fn episode(mut self, episode: i32) -> Self {
+        self.settings.episode = episode;
+        self
+    }
+
+    /// The map to start on, within the episode if the game has those.
+    pub fn map(mut self, map: i32) -> Self {
+        self.settings.map = map;
+        self
+    }
+
+    /// The skill level, like Doom's `-skill`.
+    pub fn skill(mut self, skill: Skill) -> Self {
+        self.settings.skill = skill as i32;
+        self
+    }
+
+    /// 0 for cooperative, 1 for deathmatch, 2 for altdeath and 3 for the
+    /// deathmatch 3.0 rules.
+    pub fn deathmatch(mut self, deathmatch: i32) -> Self {
+        self.settings.deathmatch = deathmatch;
+        self
+    }
+
+    /// Ends each level after this many minutes, or never if 0.
+    pub fn timelimit(mut self, timelimit: u32) -> Self {
+        self.settings.timelimit = timelimit;
+        self
+    }
+
+    /// Runs every command for this many tics, to save bandwidth.
+    pub fn ticdup(mut self, ticdup: i32) -> Self {
+        self.settings.ticdup = ticdup;
+        self
+    }
+
+    /// Sends this many older tics along with each new one, in case of loss.
+    pub fn extratics(mut self, extratics: i32) -> Self {
+        self.settings.extratics = extratics;
+        self
+    }
+
+    /// Starts without monsters, like `-nomonsters`.
+    pub fn nomonsters(mut self, nomonsters: bool) -> Self {
+        self.settings.nomonsters = nomonsters as i32;
+        self
+    }
+
+    /// Makes monsters fast, like `-fast`.
+    pub fn fast_monsters(mut self, fast_monsters: bool) -> Self {
+        self.settings.fast_monsters = fast_monsters as i32;
+        self
+    }
+
+    /// Brings killed monsters back, like `-respawn`.
+    pub fn respawn_monsters(mut self, respawn_monsters: bool) -> Self {
+        self.settings.respawn_monsters = respawn_monsters as i32;
+        self
+    }
+
+    /// The executable version to emulate, like `-gameversion`.
+    pub fn gameversion(mut self, gameversion: GameVersion) -> Self {
+        self.settings.gameversion = gameversion as i32;
+        self
+    }
+
+    /// Has every player turn in steps of 256 instead of 1.
+    pub fn lowres_turn(mut self, lowres_turn: bool) -> Self {
+        self.settings.lowres_turn = lowres_turn as i32;
+        self
+    }
+
+    /// Uses the old, lockstep clock sync like `-oldsync`.
+    pub fn old_sync(mut self, old_sync: bool) -> Self {
+        self.settings.new_sync = !old_sync as i32;
+        self
+    }
+
+    /// Finishes the settings.
+    pub fn build(self) -> GameSettings {
+        self.settings
+    }
+}
+
+/// A version of the network protocol, as named in SYN and query packets.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Protocol {
+    /// The protocol of Chocolate Doom 3.
     #[default]
     ChocolateDoom0,
+    /// One we do not speak.
     Unknown,
 }
 
+/// The packets of the game protocol.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PacketType {
+    /// Asks to join, or lets a client in when sent by the server.
     Syn,
+    /// Acknowledges a packet, no longer used.
     Ack,
+    /// Turns a client away, with the reason.
     Rejected,
+    /// Keeps an idle connection open.
     KeepAlive,
+    /// The lobby, sent while waiting for the game to start.
     WaitingData,
+    /// Starts the game, with its settings.
     GameStart,
+    /// Player commands for a run of tics.
     GameData,
+    /// Acknowledges the commands of every tic before the one given.
     GameDataAck,
+    /// Ends the connection.
     Disconnect,
+    /// Acknowledges a disconnect.
     DisconnectAck,
+    /// Acknowledges a reliable packet.
     ReliableAck,
+    /// Asks for the commands of some tics again.
     GameDataResend,
+    /// Text to show on the console.
     ConsoleMessage,
+    /// Asks a server to describe itself.
     Query,
+    /// A server's description of itself.
     QueryResponse,
+    /// Takes the lobby to the game start, before the settings are sent.
     Launch,
+    /// Opens a way through NAT towards a client.
     NatHolePunch,
 }
 
 impl PacketType {
+    /// The type with this number, if there is one.
     pub fn from_u16(value: u16) -> Option<Self> {
         match value {
             0 => Some(PacketType::Syn),
@@ -118,6 +506,7 @@ impl PacketType {
         }
     }
 
+    /// The number the type is sent as.
     pub fn to_u16(self) -> u16 {
         match self {
             PacketType::Syn => 0,
@@ -141,137 +530,441 @@ impl PacketType {
     }
 }
 
+/// The packets of the master server protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MasterPacketType {
+    /// Adds a server to the list.
+    Add,
+    /// Answers whether a server was added.
+    AddResponse,
+    /// Asks for the list of servers.
+    Query,
+    /// Part of the list of servers.
+    QueryResponse,
+    /// Asks for the servers' metadata.
+    GetMetadata,
+    /// The servers' metadata.
+    GetMetadataResponse,
+    /// Asks for a signed game start, for demos.
+    SignStart,
+    /// A signed game start.
+    SignStartResponse,
+    /// Asks for a signed demo end.
+    SignEnd,
+    /// A signed demo end.
+    SignEndResponse,
+    /// Asks a server behind NAT to open a way towards a client.
+    NatHolePunch,
+    /// Asks for a way through NAT towards every peer at once.
+    NatHolePunchAll,
+}
+
+impl MasterPacketType {
+    /// The type with this number, if there is one.
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(MasterPacketType::Add),
+            1 => Some(MasterPacketType::AddResponse),
+            2 => Some(MasterPacketType::Query),
+            3 => Some(MasterPacketType::QueryResponse),
+            4 => Some(MasterPacketType::GetMetadata),
+            5 => Some(MasterPacketType::GetMetadataResponse),
+            6 => Some(MasterPacketType::SignStart),
+            7 => Some(MasterPacketType::SignStartResponse),
+            8 => Some(MasterPacketType::SignEnd),
+            9 => Some(MasterPacketType::SignEndResponse),
+            10 => Some(MasterPacketType::NatHolePunch),
+            11 => Some(MasterPacketType::NatHolePunchAll),
+            _ => None,
+        }
+    }
+
+    /// The number the type is sent as.
+    pub fn to_u16(self) -> u16 {
+        match self {
+            MasterPacketType::Add => 0,
+            MasterPacketType::AddResponse => 1,
+            MasterPacketType::Query => 2,
+            MasterPacketType::QueryResponse => 3,
+            MasterPacketType::GetMetadata => 4,
+            MasterPacketType::GetMetadataResponse => 5,
+            MasterPacketType::SignStart => 6,
+            MasterPacketType::SignStartResponse => 7,
+            MasterPacketType::SignEnd => 8,
+            MasterPacketType::SignEndResponse => 9,
+            MasterPacketType::NatHolePunch => 10,
+            MasterPacketType::NatHolePunchAll => 11,
+        }
+    }
+}
+
+/// A command along with which of its fields changed since the last one.
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TicDiff {
+    /// `NET_TICDIFF_*` flags for the fields sent.
     pub diff: u32,
+    /// The command, with the fields not sent carried over.
     pub cmd: TicCmd,
 }
 
+/// Something that happened on a connection, as reported by
+/// [`Client::next_event`](client::Client::next_event) and to subscribers.
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// The connection moved on to another state.
+    StateChanged {
+        /// The state left.
+        from: ClientState,
+        /// The state entered.
+        to: ClientState,
+    },
+    /// The server sent lobby data that differs from the last.
+    LobbyUpdated(Lobby),
+    /// Someone showed up in the lobby.
+    PlayerJoined(LobbyPlayer),
+    /// Someone left the lobby.
+    PlayerLeft(LobbyPlayer),
+    /// The server started the game with these settings.
+    GameStarted(GameSettings),
+    /// Text the server wants shown on the console.
+    ConsoleMessage(ConsoleMessage),
+    /// The server turned us away, with the reason it gave.
+    Rejected(String),
+    /// The lobby shows other files than ours, which may make the game go
+    /// out of sync. The connection carries on regardless.
+    Sha1Mismatch {
+        /// Whether the WAD files differ.
+        wad: bool,
+        /// Whether the DeHackEd patches differ.
+        deh: bool,
+    },
+    /// Someone finished typing a line of chat.
+    Chat(ChatMessage),
+    /// The connection is gone, whoever ended it.
+    Disconnected,
+}
+
+/// The commands of every player for one tic, as the game runs them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReceivedTic {
+    /// The command of each player.
+    pub cmds: [TicCmd; NET_MAXPLAYERS],
+    /// Whether each player is in the game.
+    pub ingame: [bool; NET_MAXPLAYERS],
+}
+
+/// The commands of every player for one tic, as the server sends them.
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct FullTicCmd {
+    /// How far behind the server's clock was, in milliseconds.
     pub latency: i32,
+    /// The tic the commands are for.
     pub seq: u32,
+    /// Whether each player is in the game.
     pub playeringame: [bool; NET_MAXPLAYERS],
+    /// The command of each player in the game.
     pub cmds: [TicDiff; NET_MAXPLAYERS],
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// The lobby as the server sends it while waiting for the game to start.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WaitData {
+    /// How many players are in the lobby.
     pub num_players: i32,
+    /// How many drones are watching.
     pub num_drones: i32,
+    /// How many players are ready to start.
     pub ready_players: i32,
+    /// Most players the game can have.
     pub max_players: i32,
+    /// Non-zero if we get to launch the game.
     pub is_controller: i32,
+    /// Our player number, or -1 for drones.
     pub consoleplayer: i32,
+    /// Each player's name, NUL padded.
     pub player_names: [[char; MAXPLAYERNAME]; NET_MAXPLAYERS],
+    /// Each player's address, NUL padded.
     pub player_addrs: [[char; MAXPLAYERNAME]; NET_MAXPLAYERS],
+    /// SHA1 of the server's WAD files.
     pub wad_sha1sum: [u8; 20],
+    /// SHA1 of the server's DeHackEd patches.
     pub deh_sha1sum: [u8; 20],
+    /// Non-zero if the server runs Freedoom.
     pub is_freedoom: i32,
 }
 
+/// A server's answer to a query.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct QueryData {
+    /// The server's version string.
+    pub version: String,
+    /// 0 while in the lobby, 1 once the game has started.
+    pub server_state: i32,
+    /// How many players are in the game.
+    pub num_players: i32,
+    /// Most players the game can have.
+    pub max_players: i32,
+    /// The [`GameMode`] the server runs.
+    pub gamemode: i32,
+    /// The [`GameMission`] the server runs.
+    pub gamemission: i32,
+    /// The server's name.
+    pub description: String,
+    /// The protocol the server speaks.
+    pub protocol: Protocol,
+}
+
+/// A server found by searching.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// Where the server answered from.
+    pub addr: SocketAddr,
+    /// What it answered.
+    pub query: QueryData,
+    /// How long the answer took.
+    pub ping: Duration,
+}
+
+impl ServerInfo {
+    /// A server can be joined while it is still in the lobby and has free slots.
+    pub fn is_joinable(&self) -> bool {
+        self.query.server_state == 0
+            && self.query.num_players < self.query.max_players
+            && self.query.protocol != Protocol::Unknown
+    }
+}
+
+/// Criteria used to pick a server out of a search.
+///
+/// The query response carries the game mission and player counts, but the
+/// WAD and DEH hashes only arrive with the lobby data, so those are checked
+/// once connected.
+#[derive(Debug, Clone)]
+pub struct ServerFilter {
+    /// Only servers running this [`GameMode`].
+    pub gamemode: Option<i32>,
+    /// Only servers running this [`GameMission`].
+    pub gamemission: Option<i32>,
+    /// Only servers with at least this many free player slots.
+    pub free_slots: i32,
+    /// Only lobbies with these WAD files.
+    pub wad_sha1sum: Option<[u8; 20]>,
+    /// Only lobbies with these DeHackEd patches.
+    pub deh_sha1sum: Option<[u8; 20]>,
+}
+
+impl Default for ServerFilter {
+    fn default() -> Self {
+        Self {
+            gamemode: None,
+            gamemission: None,
+            free_slots: 1,
+            wad_sha1sum: None,
+            deh_sha1sum: None,
+        }
+    }
+}
+
+impl ServerFilter {
+    /// Whether a server found by searching can be joined and runs our game.
+    pub fn matches(&self, server: &ServerInfo) -> bool {
+        server.is_joinable()
+            && self
+                .gamemode
+                .is_none_or(|mode| mode == server.query.gamemode)
+            && self
+                .gamemission
+                .is_none_or(|mission| mission == server.query.gamemission)
+            && server.query.max_players - server.query.num_players >= self.free_slots
+    }
+
+    /// Checks the files a server's lobby runs against the ones asked for.
+    pub fn check_wait_data(&self, wait_data: &WaitData) -> Result<(), ClientError> {
+        if self
+            .wad_sha1sum
+            .is_some_and(|sha1| sha1 != wait_data.wad_sha1sum)
+        {
+            return Err(ClientError::WadMismatch);
+        }
+        if self
+            .deh_sha1sum
+            .is_some_and(|sha1| sha1 != wait_data.deh_sha1sum)
+        {
+            return Err(ClientError::DehMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The release of the game, which decides what maps there are.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameMode {
+    /// Doom shareware, with the first episode only.
     Shareware,
+    /// Registered Doom, with three episodes.
     Registered,
+    /// Doom II and Final Doom, with 32 maps and no episodes.
     Commercial,
+    /// The Ultimate Doom, with four episodes.
     Retail,
+    /// Not known.
     Indetermined,
 }
 
+impl GameMode {
+    /// The mode with this number, if there is one.
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(GameMode::Shareware),
+            1 => Some(GameMode::Registered),
+            2 => Some(GameMode::Commercial),
+            3 => Some(GameMode::Retail),
+            4 => Some(GameMode::Indetermined),
+            _ => None,
+        }
+    }
+}
+
+/// The game being played.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameMission {
+    /// Doom.
     Doom,
+    /// Doom II.
     Doom2,
+    /// Final Doom: TNT: Evilution.
     PackTnt,
+    /// Final Doom: The Plutonia Experiment.
     PackPlut,
+    /// Chex Quest.
     PackChex,
+    /// Hacx.
     PackHacx,
+    /// Heretic.
     Heretic,
+    /// Hexen.
     Hexen,
+    /// Strife.
     Strife,
+    /// No game.
     None,
 }
 
+/// The executable version whose behavior is emulated.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameVersion {
+    /// Doom 1.2.
     Doom1_2,
+    /// Doom 1.666.
     Doom1_666,
+    /// Doom 1.7.
     Doom1_7,
+    /// Doom 1.8.
     Doom1_8,
+    /// Doom 1.9.
     Doom1_9,
+    /// Hacx.
     Hacx,
+    /// The Ultimate Doom.
     Ultimate,
+    /// Final Doom.
     Final,
+    /// Final Doom as released with the id Anthology.
     Final2,
+    /// Chex Quest.
     Chex,
+    /// Heretic 1.3.
     Heretic1_3,
+    /// Hexen 1.1.
     Hexen1_1,
+    /// Strife 1.2.
     Strife1_2,
+    /// Strife 1.31.
     Strife1_31,
 }
 
+/// Which build of the IWAD is played.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameVariant {
+    /// The original IWADs.
     Vanilla,
+    /// Freedoom: Phase 1 or 2.
     Freedoom,
+    /// FreeDM.
     Freedm,
+    /// The Doom 3: BFG Edition IWADs.
     BfgEdition,
 }
 
+/// Skill levels, from the easiest.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Skill {
+    /// No things at all, like `-skill 0`.
     NoItems = -1,
+    /// I'm too young to die.
     Baby = 0,
+    /// Hey, not too rough.
     Easy,
+    /// Hurt me plenty.
     Medium,
+    /// Ultra-Violence.
     Hard,
+    /// Nightmare!
     Nightmare,
 }
 
+/// Where a connection stands, from the first SYN to the end of the game.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ClientState {
+    /// Not connected to any server.
     #[default]
     Disconnected,
+    /// Waiting for the server to let us in.
     Connecting,
+    /// Let in but not in the lobby yet. Unused, as we go straight to the
+    /// lobby.
     Connected,
+    /// In the lobby.
     WaitingLaunch,
+    /// Launched, waiting for the game settings.
     WaitingStart,
+    /// Playing.
     InGame,
+    /// Waiting for the server to acknowledge that we leave.
     Disconnecting,
 }
 
 #[derive(Clone, Copy)]
-pub struct ServerRecv {
+pub(crate) struct ServerRecv {
     pub active: bool,
     pub resend_time: Instant,
     pub cmd: FullTicCmd,
 }
 
-impl Default for ServerRecv {
-    fn default() -> Self {
+impl ServerRecv {
+    pub fn new(now: Instant) -> Self {
         Self {
             active: false,
-            resend_time: Instant::now(),
+            resend_time: now,
             cmd: Default::default(),
         }
     }
 }
 
 #[derive(Clone, Copy)]
-pub struct ServerSend {
+pub(crate) struct ServerSend {
     pub active: bool,
     pub seq: u32,
     pub time: Instant,
     pub cmd: TicDiff,
 }
 
-impl Default for ServerSend {
-    fn default() -> Self {
+impl ServerSend {
+    pub fn new(now: Instant) -> Self {
         Self {
             active: false,
             seq: 0,
-            time: Instant::now(),
+            time: now,
             cmd: Default::default(),
         }
     }
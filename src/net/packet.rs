@@ -3,13 +3,18 @@ use std::convert::TryInto;
 
 use super::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A packet being built or read, in network byte order.
+///
+/// Reads start at the front, advance past what they read and return `None`
+/// once the data runs out.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Packet {
-    pub data: Vec<u8>,
-    pub pos: usize,
+    data: Vec<u8>,
+    pos: usize,
 }
 
 impl Packet {
+    /// An empty packet to write to.
     pub fn new() -> Self {
         Packet {
             data: Vec::new(),
@@ -17,12 +22,27 @@ impl Packet {
         }
     }
 
+    /// A packet to read `data` from.
+    pub fn from_data(data: &[u8]) -> Self {
+        Packet {
+            data: data.to_vec(),
+            pos: 0,
+        }
+    }
+
+    /// Everything written so far, or the whole packet being read.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Writes `data` as is.
     pub fn write_blob(&mut self, data: &[u8]) {
         self.data.extend_from_slice(data);
     }
 
+    /// Writes two bytes.
     pub fn write_u16(&mut self, value: u16) {
-        self.data.extend_from_slice(&value.to_le_bytes());
+        self.data.extend_from_slice(&value.to_be_bytes());
     }
 
     fn read_ticcmd_diff(&mut self, lowres_turn: bool) -> Option<TicDiff> {
@@ -78,31 +98,38 @@ impl Packet {
         Some(diff)
     }
 
+    /// Writes a byte.
     pub fn write_u8(&mut self, value: u8) {
         self.data.push(value);
     }
 
+    /// Writes a signed byte.
     pub fn write_i8(&mut self, value: i8) {
         self.write_u8(value as u8);
     }
 
+    /// Writes two bytes, signed.
     pub fn write_i16(&mut self, value: i16) {
-        self.data.extend_from_slice(&value.to_le_bytes());
+        self.data.extend_from_slice(&value.to_be_bytes());
     }
 
+    /// Writes four bytes.
     pub fn write_u32(&mut self, value: u32) {
-        self.data.extend_from_slice(&value.to_le_bytes());
+        self.data.extend_from_slice(&value.to_be_bytes());
     }
 
+    /// Writes four bytes, signed.
     pub fn write_i32(&mut self, value: i32) {
-        self.data.extend_from_slice(&value.to_le_bytes());
+        self.data.extend_from_slice(&value.to_be_bytes());
     }
 
+    /// Writes `s` with a NUL after it.
     pub fn write_string(&mut self, s: &str) {
         self.data.extend_from_slice(s.as_bytes());
         self.data.push(0); // Null terminator
     }
 
+    /// Reads a byte.
     pub fn read_u8(&mut self) -> Option<u8> {
         if self.pos < self.data.len() {
             let value = self.data[self.pos];
@@ -113,10 +140,12 @@ impl Packet {
         }
     }
 
+    /// Reads a signed byte.
     pub fn read_i8(&mut self) -> Option<i8> {
         self.read_u8().map(|v| v as i8)
     }
 
+    /// Reads two bytes.
     pub fn read_u16(&mut self) -> Option<u16> {
         if self.pos + 2 <= self.data.len() {
             let bytes = &self.data[self.pos..self.pos + 2];
@@ -127,10 +156,12 @@ impl Packet {
         }
     }
 
+    /// Reads two bytes, signed.
     pub fn read_i16(&mut self) -> Option<i16> {
         self.read_u16().map(|v| v as i16)
     }
 
+    /// Reads four bytes.
     pub fn read_u32(&mut self) -> Option<u32> {
         if self.pos + 4 <= self.data.len() {
             let bytes = &self.data[self.pos..self.pos + 4];
@@ -141,10 +172,12 @@ impl Packet {
         }
     }
 
+    /// Reads four bytes, signed.
     pub fn read_i32(&mut self) -> Option<i32> {
         self.read_u32().map(|v| v as i32)
     }
 
+    /// Reads up to the next NUL, which is skipped.
     pub fn read_string(&mut self) -> Option<String> {
         if let Some(terminator) = self.data[self.pos..].iter().position(|&c| c == 0) {
             let bytes = &self.data[self.pos..self.pos + terminator];
@@ -156,6 +189,7 @@ impl Packet {
         }
     }
 
+    /// Like [`Packet::read_string`], dropping control characters.
     pub fn read_safe_string(&mut self) -> Option<String> {
         self.read_string().map(|s| {
             s.chars()
@@ -174,6 +208,7 @@ impl Packet {
         }
     }
 
+    /// Reads a protocol name.
     pub fn read_protocol(&mut self) -> Protocol {
         if let Some(name) = self.read_string() {
             match name.as_str() {
@@ -185,15 +220,48 @@ impl Packet {
         }
     }
 
-    pub fn write_protocol(&mut self, protocol: Protocol) {
+    /// Reads a list of protocol names, returning the first one we speak.
+    pub fn read_protocol_list(&mut self) -> Protocol {
+        let num_protocols = self.read_u8().unwrap_or(0);
+        let mut result = Protocol::Unknown;
+
+        // Keep reading so the packet position ends up after the list.
+        for _ in 0..num_protocols {
+            let protocol = self.read_protocol();
+            if result == Protocol::Unknown {
+                result = protocol;
+            }
+        }
+
+        result
+    }
+
+    /// Writes a list of the one protocol given. `Protocol::Unknown` has no
+    /// name to write, so it is refused and nothing is written.
+    pub fn write_protocol(&mut self, protocol: Protocol) -> Result<(), ClientError> {
         let name = match protocol {
             Protocol::ChocolateDoom0 => "CHOCOLATE_DOOM_0",
-            _ => panic!("NET_WriteProtocol: Unknown protocol {:?}", protocol),
+            Protocol::Unknown => return Err(ClientError::Protocol),
         };
         self.write_u8(1); // Number of protocols
         self.write_string(name);
+        Ok(())
+    }
+
+    /// Writes what a SYN tells the server about our game.
+    pub fn write_connect_data(&mut self, data: &ConnectData) {
+        self.write_u8(data.gamemode as u8);
+        self.write_u8(data.gamemission as u8);
+        self.write_u8(data.lowres_turn as u8);
+        self.write_u8(data.drone as u8);
+        self.write_u8(data.max_players as u8);
+        self.write_u8(data.is_freedoom as u8);
+        self.write_blob(&data.wad_sha1sum);
+        self.write_blob(&data.deh_sha1sum);
+        self.write_u8(data.player_class as u8);
     }
 
+    /// Reads the lobby from waiting data.
     pub fn read_wait_data(&mut self) -> Option<WaitData> {
         let mut data = WaitData {
             num_players: self.read_u8()? as i32,
@@ -204,6 +272,9 @@ impl Packet {
             consoleplayer: self.read_i8()? as i32,
             ..Default::default()
         };
+        if data.num_players as usize > NET_MAXPLAYERS {
+            return None;
+        }
         for i in 0..data.num_players as usize {
             let name = self.read_string()?;
             if name.len() >= MAXPLAYERNAME {
@@ -228,6 +299,27 @@ impl Packet {
         Some(data)
     }
 
+    /// Reads a server's answer to a query.
+    pub fn read_query_data(&mut self) -> Option<QueryData> {
+        let mut query = QueryData {
+            version: self.read_safe_string()?,
+            server_state: self.read_u8()? as i32,
+            num_players: self.read_u8()? as i32,
+            max_players: self.read_u8()? as i32,
+            gamemode: self.read_u8()? as i32,
+            gamemission: self.read_u8()? as i32,
+            description: self.read_safe_string()?,
+            ..Default::default()
+        };
+
+        // Old versions of Chocolate Doom do not send the protocol list, so
+        // it is fine if it cannot be read.
+        query.protocol = self.read_protocol_list();
+
+        Some(query)
+    }
+
+    /// Reads the settings a game starts with.
     pub fn read_settings(&mut self) -> Option<GameSettings> {
         let mut settings = GameSettings {
             ticdup: self.read_u8()? as i32,
@@ -249,12 +341,44 @@ impl Packet {
             consoleplayer: self.read_i8()? as i32,
             ..Default::default()
         };
+        if settings.num_players as usize > NET_MAXPLAYERS {
+            return None;
+        }
         for i in 0..settings.num_players as usize {
             settings.player_classes[i] = self.read_u8()? as i32;
         }
         Some(settings)
     }
 
+    /// Writes the settings to start a game with.
+    pub fn write_settings(&mut self, settings: &GameSettings) {
+        self.write_u8(settings.ticdup as u8);
+        self.write_u8(settings.extratics as u8);
+        self.write_u8(settings.deathmatch as u8);
+        self.write_u8(settings.nomonsters as u8);
+        self.write_u8(settings.fast_monsters as u8);
+        self.write_u8(settings.respawn_monsters as u8);
+        self.write_u8(settings.episode as u8);
+        self.write_u8(settings.map as u8);
+        self.write_i8(settings.skill as i8);
+        self.write_u8(settings.gameversion as u8);
+        self.write_u8(settings.lowres_turn as u8);
+        self.write_u8(settings.new_sync as u8);
+        self.write_u32(settings.timelimit);
+        self.write_i8(settings.loadgame as i8);
+        self.write_u8(settings.random as u8);
+        self.write_u8(settings.num_players as u8);
+        self.write_i8(settings.consoleplayer as i8);
+        for class in settings
+            .player_classes
+            .iter()
+            .take(settings.num_players.max(0) as usize)
+        {
+            self.write_u8(*class as u8);
+        }
+    }
+
+    /// Reads the commands of every player in the game for one tic.
     pub fn read_full_ticcmd(&mut self, lowres_turn: bool) -> Option<FullTicCmd> {
         let mut cmd = FullTicCmd {
             latency: self.read_i16()? as i32,
@@ -274,6 +398,7 @@ impl Packet {
         Some(cmd)
     }
 
+    /// Writes the fields of a command flagged in `diff.diff`.
     pub fn write_ticcmd_diff(&mut self, diff: &TicDiff, lowres_turn: bool) {
         self.write_u8(diff.diff as u8);
 
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use crate::clock::Clock;
+
+/// How far behind the scheduler may fall before it gives up on the missed
+/// wakeups and realigns to the tic grid, instead of firing them back to back.
+const MAX_CATCHUP_TICS: u32 = 2;
+
+/// Keeps the game loop on the tic grid.
+///
+/// Deadlines are absolute multiples of the tic period from the start time,
+/// so oversleeping one tic does not push every later tic back.
+pub struct TicScheduler {
+    clock: Arc<dyn Clock>,
+    period: Duration,
+    start_time: Instant,
+    next_tic: u64,
+    stats: JitterStats,
+}
+
+/// How late the scheduler woke up for its tics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JitterStats {
+    /// How many tics fired.
+    pub tics: u64,
+    /// How many tics were given up on after falling too far behind.
+    pub skipped_tics: u64,
+    /// The latest a tic fired.
+    pub max_late: Duration,
+    total_late: Duration,
+    total_late_sq_us: f64,
+}
+
+impl JitterStats {
+    fn record(&mut self, late: Duration) {
+        self.tics += 1;
+        self.max_late = self.max_late.max(late);
+        self.total_late += late;
+        self.total_late_sq_us += late.as_secs_f64().powi(2) * 1e12;
+    }
+
+    /// How late the tics fired on average.
+    pub fn mean_late(&self) -> Duration {
+        if self.tics == 0 {
+            return Duration::ZERO;
+        }
+        self.total_late / self.tics as u32
+    }
+
+    /// Logs the numbers.
+    pub fn report(&self) {
+        info!(
+            tics = self.tics,
+            skipped = self.skipped_tics,
+            mean_late_us = self.mean_late().as_micros() as u64,
+            stddev_late_us = self.stddev_late().as_micros() as u64,
+            max_late_us = self.max_late.as_micros() as u64,
+            "Tic scheduler jitter"
+        );
+    }
+
+    /// How much the lateness varied.
+    pub fn stddev_late(&self) -> Duration {
+        if self.tics == 0 {
+            return Duration::ZERO;
+        }
+        let mean_us = self.mean_late().as_secs_f64() * 1e6;
+        let variance = (self.total_late_sq_us / self.tics as f64 - mean_us * mean_us).max(0.0);
+        Duration::from_secs_f64(variance.sqrt() / 1e6)
+    }
+}
+
+impl TicScheduler {
+    /// A scheduler firing `ticrate` times a second, starting now.
+    pub fn new(clock: Arc<dyn Clock>, ticrate: u32) -> Self {
+        let start_time = clock.now();
+
+        TicScheduler {
+            clock,
+            period: Duration::from_secs(1) / ticrate,
+            start_time,
+            next_tic: 1,
+            stats: JitterStats::default(),
+        }
+    }
+
+    fn deadline(&self, tic: u64) -> Instant {
+        self.start_time + self.period * tic as u32
+    }
+
+    /// Time left until the next tic is due, zero if it already is.
+    pub fn until_next_tic(&self) -> Duration {
+        self.deadline(self.next_tic)
+            .saturating_duration_since(self.clock.now())
+    }
+
+    /// Returns true once per due tic. After a stall of more than
+    /// `MAX_CATCHUP_TICS` the missed tics are counted as skipped and the
+    /// schedule continues from the current tic, leaving the game to catch up
+    /// on its own from the elapsed time.
+    pub fn poll(&mut self) -> bool {
+        let now = self.clock.now();
+        let due = self.deadline(self.next_tic);
+        if now < due {
+            return false;
+        }
+
+        let late = now - due;
+        let behind = (late.as_nanos() / self.period.as_nanos()) as u64;
+
+        self.stats.record(late);
+
+        if behind > MAX_CATCHUP_TICS as u64 {
+            self.stats.skipped_tics += behind;
+            self.next_tic += behind;
+        }
+        self.next_tic += 1;
+
+        true
+    }
+
+    /// How late the tics fired so far.
+    pub fn get_stats(&self) -> &JitterStats {
+        &self.stats
+    }
+}
@@ -0,0 +1,122 @@
+use hydra_bot::action::{ActionCompiler, Intent, Special, Turn, Weapon};
+use hydra_bot::net::{BTS_PAUSE, BTS_SAVEGAME, BT_ATTACK, BT_CHANGE, BT_SPECIAL, BT_USE};
+
+fn turns(compiler: &mut ActionCompiler, turn: Turn, tics: usize) -> Vec<i16> {
+    (0..tics)
+        .map(|_| {
+            compiler
+                .compile(&Intent {
+                    turn,
+                    ..Default::default()
+                })
+                .angleturn
+        })
+        .collect()
+}
+
+fn buttons(intent: Intent) -> u8 {
+    ActionCompiler::default().compile(&intent).buttons
+}
+
+#[test]
+fn movement_is_scaled_to_the_game_speeds() {
+    let cmd = ActionCompiler::default().compile(&Intent {
+        forward: 1.0,
+        strafe: -1.0,
+        run: true,
+        ..Default::default()
+    });
+    assert_eq!((cmd.forwardmove, cmd.sidemove), (0x32, -0x28));
+
+    let cmd = ActionCompiler::default().compile(&Intent {
+        forward: -2.0,
+        strafe: 0.5,
+        ..Default::default()
+    });
+    assert_eq!((cmd.forwardmove, cmd.sidemove), (-0x19, 0x0c));
+}
+
+#[test]
+fn full_resolution_turns_are_exact() {
+    let mut compiler = ActionCompiler::new(false);
+    assert_eq!(turns(&mut compiler, Turn::By(1.0), 4), [182; 4]);
+}
+
+#[test]
+fn low_resolution_turns_carry_their_rounding() {
+    let mut compiler = ActionCompiler::new(true);
+
+    // One degree is 182 units, which rounds to 256 or 0, with the error
+    // made up for on the following tics.
+    let angleturns = turns(&mut compiler, Turn::By(1.0), 4);
+    assert_eq!(angleturns, [256, 0, 256, 256]);
+    let total: i32 = angleturns.iter().map(|&turn| turn as i32).sum();
+    assert!((total - 4 * 182).abs() <= 128);
+}
+
+#[test]
+fn turns_to_an_angle_are_relative_to_the_heading() {
+    let mut compiler = ActionCompiler::new(false);
+
+    assert_eq!(turns(&mut compiler, Turn::To(90.0), 1), [16384]);
+    assert_eq!(compiler.get_heading(), 90.0);
+    assert_eq!(turns(&mut compiler, Turn::To(90.0), 1), [0]);
+    assert_eq!(turns(&mut compiler, Turn::To(45.0), 1), [-8192]);
+    assert_eq!(compiler.get_heading(), 45.0);
+    // Angles wrap around.
+    assert_eq!(turns(&mut compiler, Turn::To(-315.0), 1), [0]);
+}
+
+#[test]
+fn buttons_are_combined() {
+    let intent = Intent {
+        fire: true,
+        activate: true,
+        ..Default::default()
+    };
+    assert_eq!(buttons(intent), BT_ATTACK | BT_USE);
+}
+
+#[test]
+fn weapons_are_changed_to_by_slot() {
+    let slots: Vec<_> = [
+        Weapon::Fist,
+        Weapon::Shotgun,
+        Weapon::SuperShotgun,
+        Weapon::Chainsaw,
+    ]
+    .into_iter()
+    .map(|weapon| {
+        buttons(Intent {
+            weapon: Some(weapon),
+            ..Default::default()
+        })
+    })
+    .collect();
+    assert_eq!(
+        slots,
+        [
+            BT_CHANGE,
+            BT_CHANGE | 2 << 3,
+            BT_CHANGE | 2 << 3,
+            BT_CHANGE | 7 << 3
+        ]
+    );
+}
+
+#[test]
+fn specials_replace_the_other_buttons() {
+    let pause = Intent {
+        fire: true,
+        weapon: Some(Weapon::Pistol),
+        special: Some(Special::Pause),
+        ..Default::default()
+    };
+    assert_eq!(buttons(pause), BT_SPECIAL | BTS_PAUSE);
+
+    let save = Intent {
+        special: Some(Special::SaveGame(3)),
+        ..Default::default()
+    };
+    assert_eq!(buttons(save), BT_SPECIAL | BTS_SAVEGAME | 3 << 2);
+}
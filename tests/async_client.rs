@@ -0,0 +1,381 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use hydra_bot::brain::ForwardBrain;
+use hydra_bot::net::{ClientEvent, ClientState, ConnectData, GameSettings, ServerNotice, Skill};
+use hydra_bot::ClientError;
+use tokio::time;
+
+use common::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plays_a_game() {
+    let mut desc = ServerDescription::new("Async server");
+    desc.start_game = Some(0);
+    let server = spawn_server(desc);
+
+    let mut client = new_client("AsyncBot");
+    let connect_data = ConnectData::builder([0; 20]).drone(false).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+
+    let bot = tokio::spawn(async move { client.play(Box::new(ForwardBrain)).await });
+
+    let data = tokio::task::spawn_blocking(move || {
+        wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(10))
+    })
+    .await
+    .unwrap();
+    bot.abort();
+
+    let data = data.expect("bot sent no commands");
+    assert_eq!(first_tic(&data), (0, 1, Some(0x32)));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn many_bots_share_one_thread() {
+    let server = spawn_server(ServerDescription::new("Crowded server"));
+    let addr = server.addr;
+
+    let bots: Vec<_> = (0..32)
+        .map(|i| {
+            tokio::spawn(async move {
+                let mut client = new_client(&format!("Bot{}", i));
+                client
+                    .connect(addr, ConnectData::builder([0; 20]).build())
+                    .await
+            })
+        })
+        .collect();
+
+    for bot in bots {
+        bot.await.unwrap().unwrap();
+    }
+
+    let syns = server
+        .packets
+        .try_iter()
+        .filter(|(packet_type, _)| *packet_type == PACKET_TYPE_SYN)
+        .count();
+    assert!(syns >= 32, "only {} bots connected", syns);
+}
+
+#[tokio::test]
+async fn events_follow_the_connection() {
+    let mut desc = ServerDescription::new("Eventful server");
+    desc.start_game = Some(-1);
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Watcher");
+    let mut events = client.subscribe();
+    let connect_data = ConnectData::builder([0; 20]).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+
+    let mut seen = Vec::new();
+    while let Some(event) = client.next_event().await {
+        let started = matches!(event, ClientEvent::GameStarted(_));
+        seen.push(event);
+        if started {
+            break;
+        }
+    }
+
+    // Subscribers see the same events as the client's own queue.
+    let mut copies = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        copies.push(event);
+    }
+    assert_eq!(format!("{:?}", seen), format!("{:?}", copies));
+
+    let states: Vec<_> = seen
+        .iter()
+        .filter_map(|event| match event {
+            ClientEvent::StateChanged { to, .. } => Some(*to),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        states,
+        [
+            ClientState::Connecting,
+            ClientState::WaitingLaunch,
+            ClientState::InGame
+        ]
+    );
+    assert!(seen
+        .iter()
+        .any(|event| matches!(event, ClientEvent::LobbyUpdated(_))));
+}
+
+#[tokio::test]
+async fn rejections_are_reported() {
+    let mut desc = ServerDescription::new("Full server");
+    desc.reject = Some("Server is full!".to_string());
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Turned away");
+    let mut events = client.subscribe();
+    let result = client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await;
+
+    assert!(matches!(result, Err(ClientError::Rejected(_))));
+    let mut rejected = None;
+    while let Ok(event) = events.try_recv() {
+        if let ClientEvent::Rejected(reason) = event {
+            rejected = Some(reason);
+        }
+    }
+    assert_eq!(rejected.as_deref(), Some("Server is full!"));
+}
+
+#[tokio::test]
+async fn lobby_lists_players() {
+    let desc = ServerDescription::new("Lobby server");
+    let players = desc.players.clone();
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Watcher");
+    let mut events = client.subscribe();
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+
+    let lobby = client.wait_for_lobby(|_| true).await.unwrap();
+    assert_eq!(lobby.players.len(), 1);
+    assert_eq!(lobby.players[0].name, "Player");
+    assert_eq!(lobby.players[0].address, "127.0.0.1");
+    assert_eq!(lobby.local_slot, None);
+    assert!(!lobby.is_controller);
+
+    *players.lock().unwrap() = vec!["Player".to_string(), "Alice".to_string()];
+    let lobby = time::timeout(
+        Duration::from_secs(5),
+        client.wait_for_lobby(|lobby| lobby.has_player("Alice")),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(lobby.get_player("Alice").unwrap().slot, 1);
+
+    // Alice moves up a slot without being reported as joining again.
+    *players.lock().unwrap() = vec!["Alice".to_string()];
+    let lobby = time::timeout(
+        Duration::from_secs(5),
+        client.wait_for_lobby(|lobby| !lobby.has_player("Player")),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(lobby.get_player("Alice").unwrap().slot, 0);
+
+    let mut changes = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        match event {
+            ClientEvent::PlayerJoined(player) => changes.push(format!("+{}", player.name)),
+            ClientEvent::PlayerLeft(player) => changes.push(format!("-{}", player.name)),
+            _ => {}
+        }
+    }
+    assert_eq!(changes, ["+Player", "+Alice", "-Player"]);
+}
+
+#[tokio::test]
+async fn players_with_the_same_name_and_address_are_counted() {
+    let desc = ServerDescription::new("Crowded server");
+    let players = desc.players.clone();
+    *players.lock().unwrap() = vec!["Bot".to_string(), "Bot".to_string()];
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Watcher");
+    let mut events = client.subscribe();
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+    client.wait_for_lobby(|_| true).await.unwrap();
+
+    // One of the two leaves, which the other one moving up must not hide.
+    *players.lock().unwrap() = vec!["Bot".to_string()];
+    let lobby = time::timeout(
+        Duration::from_secs(5),
+        client.wait_for_lobby(|lobby| lobby.players.len() == 1),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(lobby.players[0].name, "Bot");
+
+    let mut changes = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        match event {
+            ClientEvent::PlayerJoined(player) => changes.push(format!("+{}", player.name)),
+            ClientEvent::PlayerLeft(player) => changes.push(format!("-{}", player.name)),
+            _ => {}
+        }
+    }
+    assert_eq!(changes, ["+Bot", "+Bot", "-Bot"]);
+}
+
+#[tokio::test]
+async fn other_files_on_the_server_are_reported_once() {
+    let mut desc = ServerDescription::new("Modded server");
+    desc.wad_sha1sum = [0xaa; 20];
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Watcher");
+    let mut events = client.subscribe();
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+    // The lobby data keeps coming, but only the first is checked.
+    time::timeout(Duration::from_secs(3), async {
+        while client.next_event().await.is_some() {}
+    })
+    .await
+    .unwrap_err();
+    assert!(client.get_client().is_connected());
+
+    let mut mismatches = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let ClientEvent::Sha1Mismatch { wad, deh } = event {
+            mismatches.push((wad, deh));
+        }
+    }
+    assert_eq!(mismatches, [(true, false)]);
+}
+
+#[tokio::test]
+async fn controller_starts_the_game() {
+    let mut desc = ServerDescription::new("Bot match");
+    desc.controller = true;
+    desc.ignore_first_launch = true;
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Controller");
+    let connect_data = ConnectData::builder([0; 20]).drone(false).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+
+    let settings = GameSettings::builder()
+        .map(7)
+        .skill(Skill::Hard)
+        .deathmatch(1)
+        .timelimit(10)
+        .nomonsters(true)
+        .build();
+    client.start_game_with(1, settings).await.unwrap();
+
+    let started = time::timeout(Duration::from_secs(5), async {
+        loop {
+            match client.next_event().await {
+                Some(ClientEvent::GameStarted(settings)) => return Some(settings),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+    })
+    .await
+    .unwrap()
+    .expect("game did not start");
+
+    assert_eq!(
+        (started.map, started.skill, started.deathmatch),
+        (7, Skill::Hard as i32, 1)
+    );
+    assert_eq!((started.timelimit, started.nomonsters), (10, 1));
+    assert_eq!((started.num_players, started.consoleplayer), (1, 0));
+}
+
+#[tokio::test]
+async fn only_the_controller_starts_games() {
+    let server = spawn_server(ServerDescription::new("Someone else's match"));
+
+    let mut client = new_client("Guest");
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+    client.wait_for_lobby(|_| true).await.unwrap();
+
+    let result = client.start_game(GameSettings::builder().build()).await;
+    assert!(matches!(result, Err(ClientError::NotController)));
+}
+
+#[tokio::test]
+async fn console_messages_are_parsed() {
+    let server = spawn_server(ServerDescription::new("Talkative server"));
+
+    let mut client = new_client("Reader");
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+
+    let before = SystemTime::now();
+    let texts = [
+        "Player 2 (Alice) left the game.",
+        "Client 'Bob' timed out and disconnected",
+        "Player 2 (Alice) has joined the game.",
+        "Warning: WAD SHA1 checksums do not match!",
+        "Carol (the quiet one) left the game.",
+        "Don't quit 'til it's done",
+        "Welcome!",
+    ];
+    for text in texts {
+        server.send_to_client(&console_message(text));
+    }
+
+    let mut messages = Vec::new();
+    time::timeout(Duration::from_secs(5), async {
+        while messages.len() < texts.len() {
+            if let Some(ClientEvent::ConsoleMessage(message)) = client.next_event().await {
+                messages.push(message);
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    // Only the two notices Chocolate Doom sends are taken for one.
+    let notices: Vec<_> = messages.iter().map(|m| m.notice.clone()).collect();
+    let left = |name: &str| {
+        Some(ServerNotice::PlayerLeft {
+            name: name.to_string(),
+        })
+    };
+    assert_eq!(
+        notices,
+        [left("Alice"), left("Bob"), None, None, None, None, None]
+    );
+    assert_eq!(messages[6].text, "Welcome!");
+    assert!(messages.iter().all(|m| m.time >= before));
+}
+
+#[tokio::test]
+async fn disconnect_waits_for_the_acknowledgement() {
+    let mut desc = ServerDescription::new("Slow server");
+    desc.ignore_first_disconnect = true;
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Leaver");
+    let connect_data = ConnectData::builder([0; 20]).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+
+    let start = time::Instant::now();
+    client.disconnect().await;
+    let elapsed = start.elapsed();
+
+    assert_eq!(client.get_client().get_state(), ClientState::Disconnected);
+    assert!(!client.get_client().is_connected());
+    // Only the resent disconnect got an answer.
+    assert!(elapsed >= Duration::from_secs(1), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    let disconnects = server
+        .packets
+        .try_iter()
+        .filter(|(packet_type, _)| *packet_type == PACKET_TYPE_DISCONNECT)
+        .count();
+    assert_eq!(disconnects, 2);
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::*;
+
+/// Collects the SYNs of `count` bots, returning them sorted by name.
+fn wait_for_syns(server: &StandInServer, count: usize) -> Vec<Syn> {
+    let mut syns: Vec<Syn> = Vec::new();
+    while syns.len() < count {
+        let Some(data) = wait_for_packet(server, PACKET_TYPE_SYN, Duration::from_secs(10)) else {
+            break;
+        };
+        let syn = parse_syn(&data);
+        // Bots resend their SYN until the server answers.
+        if !syns.iter().any(|other| other.name == syn.name) {
+            syns.push(syn);
+        }
+    }
+    syns.sort_by(|a, b| a.name.cmp(&b.name));
+    syns
+}
+
+#[test]
+fn count_runs_several_bots() {
+    let server = spawn_server(ServerDescription::new("Busy server"));
+    let wad = dummy_wad("count");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--count", "3"]);
+
+    let syns = wait_for_syns(&server, 3);
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    let names: Vec<_> = syns.iter().map(|syn| syn.name.as_str()).collect();
+    assert_eq!(names, ["HydraBot1", "HydraBot2", "HydraBot3"]);
+}
+
+#[test]
+fn manifest_describes_each_bot() {
+    let server = spawn_server(ServerDescription::new("Busy server"));
+    let wad = dummy_wad("manifest");
+    let manifest =
+        std::env::temp_dir().join(format!("hydra-bot-manifest-{}.toml", std::process::id()));
+    std::fs::write(
+        &manifest,
+        "[[bot]]\nname = \"Alice\"\nplayer = true\nplayer-class = 2\n\n\
+         [[bot]]\nname = \"Bob\"\nbrain = \"idle\"\n",
+    )
+    .unwrap();
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    let syns = wait_for_syns(&server, 2);
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert_eq!(syns.len(), 2, "not every bot connected");
+    assert_eq!(
+        (syns[0].name.as_str(), syns[0].drone, syns[0].player_class),
+        ("Alice", 0, 2)
+    );
+    assert_eq!(
+        (syns[1].name.as_str(), syns[1].drone, syns[1].player_class),
+        ("Bob", 1, 0)
+    );
+}
+
+#[test]
+fn logs_are_tagged_with_the_bot_name() {
+    let mut desc = ServerDescription::new("Full server");
+    desc.reject = Some("Server is full!".to_string());
+    let server = spawn_server(desc);
+    let wad = dummy_wad("tagged");
+
+    let address = server.addr.to_string();
+    let output = run_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--count", "2"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for name in ["HydraBot1", "HydraBot2"] {
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.contains(name) && line.contains("Connection rejected")),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn console_messages_are_logged() {
+    let mut desc = ServerDescription::new("Talkative server");
+    desc.console_message = Some("Welcome to the\nserver!".to_string());
+    let server = spawn_server(desc);
+    let wad = dummy_wad("console");
+    let log = std::env::temp_dir().join(format!("hydra-bot-console-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--console-log",
+        log.to_str().unwrap(),
+    ]);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut contents = String::new();
+    while !contents.ends_with('\n') && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+        contents = std::fs::read_to_string(&log).unwrap_or_default();
+    }
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    let (time, line) = contents.split_once(' ').expect("nothing logged");
+    assert!(time.parse::<f64>().is_ok(), "{}", contents);
+    assert_eq!(line, "HydraBot: Welcome to the server!\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn terminated_bots_leave_their_server() {
+    let server = spawn_server(ServerDescription::new("Busy server"));
+    let wad = dummy_wad("terminate");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--count", "2"]);
+
+    let syns = wait_for_syns(&server, 2);
+    assert_eq!(syns.len(), 2, "bots never joined");
+    // Give both a moment to be let in.
+    thread::sleep(Duration::from_millis(500));
+
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &bot.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let exited = wait_for_exit(&mut bot, Duration::from_secs(10));
+
+    let disconnects = server
+        .packets
+        .try_iter()
+        .filter(|(packet_type, _)| *packet_type == PACKET_TYPE_DISCONNECT)
+        .count();
+    assert!(
+        exited.is_some_and(|status| status.success()),
+        "{:?}",
+        exited
+    );
+    assert_eq!(disconnects, 2);
+}
@@ -0,0 +1,9 @@
+use hydra_bot::brain::{self, BRAIN_NAMES};
+
+#[test]
+fn brains_are_picked_by_name() {
+    for name in BRAIN_NAMES {
+        assert!(brain::from_name(name).is_some(), "no brain named {}", name);
+    }
+    assert!(brain::from_name("psychic").is_none());
+}
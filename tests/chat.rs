@@ -0,0 +1,385 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hydra_bot::brain::{Brain, ForwardBrain, IdleBrain, Observation};
+use hydra_bot::chat::{ChatAssembler, ChatDestination, HU_BROADCAST, KEY_BACKSPACE, KEY_ENTER};
+use hydra_bot::command::BotCommand;
+use hydra_bot::net::{
+    ClientEvent, ConnectData, GameMode, TicCmd, BTS_PAUSE, BT_CHANGE, BT_SPECIAL,
+};
+use tokio::time;
+
+use common::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chat_is_sent_a_character_per_tic() {
+    let mut desc = ServerDescription::new("Chatty server");
+    desc.start_game = Some(0);
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Talker");
+    let connect_data = ConnectData::builder([0; 20]).drone(false).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+    client.send_chat(ChatDestination::Player(1), "Hi, you!");
+
+    let bot = tokio::spawn(async move { client.play(Box::new(IdleBrain)).await });
+
+    let sent = tokio::task::spawn_blocking(move || {
+        let mut sent = Vec::new();
+        let mut next = 0;
+        while sent.last().is_none_or(|&(_, c)| c != KEY_ENTER) {
+            let Some(data) =
+                wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5))
+            else {
+                break;
+            };
+            next = answer_tics(&server, &data, next, 0);
+            for tic in sent_tics(&data) {
+                let chat = tic.chatchar.map(|c| (tic.tic, c));
+                if let Some(chat) = chat.filter(|chat| !sent.contains(chat)) {
+                    sent.push(chat);
+                }
+            }
+        }
+        sent
+    })
+    .await
+    .unwrap();
+    bot.abort();
+
+    let tics: Vec<_> = sent.iter().map(|&(tic, _)| tic).collect();
+    assert_eq!(tics, (0..tics.len() as u8).collect::<Vec<_>>());
+    let chars: Vec<_> = sent.iter().map(|&(_, c)| c).collect();
+    assert_eq!(chars, b"\x02HI, YOU!\r");
+}
+
+#[test]
+fn lowercase_chat_is_shown_as_capitals() {
+    let mut assembler = ChatAssembler::default();
+    let line = [HU_BROADCAST]
+        .into_iter()
+        .chain(*b"gg, wp")
+        .chain([KEY_ENTER])
+        .filter_map(|c| assembler.receive(2, c))
+        .collect::<Vec<_>>();
+    assert_eq!(line, [(ChatDestination::Everyone, "GG, WP".to_string())]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chat_is_put_together_from_the_tics() {
+    let mut desc = ServerDescription::new("Chatty server");
+    desc.start_game = Some(-1);
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Listener");
+    let mut events = client.subscribe();
+    client
+        .connect(server.addr, ConnectData::builder([0; 20]).build())
+        .await
+        .unwrap();
+    let bot = tokio::spawn(async move { client.play(Box::new(IdleBrain)).await });
+
+    // Tics sent before the game start would be dropped with the old game.
+    while !matches!(
+        events.recv().await,
+        Some(ClientEvent::GameStarted(_)) | None
+    ) {}
+
+    let typed = [
+        HU_BROADCAST,
+        b'H',
+        b'E',
+        b'X',
+        KEY_BACKSPACE,
+        b'L',
+        b'L',
+        b'O',
+        KEY_ENTER,
+    ];
+    for (tic, &c) in typed.iter().enumerate() {
+        let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+        // seq, tic count, latency, players in game, diff, chatchar
+        data.extend_from_slice(&[tic as u8, 1, 0, 0, 0x01, 0x20, c]);
+        server.send_to_client(&data);
+    }
+
+    let message = time::timeout(Duration::from_secs(5), async {
+        loop {
+            match events.recv().await {
+                Some(ClientEvent::Chat(message)) => return Some(message),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+    })
+    .await
+    .unwrap()
+    .expect("no chat received");
+    bot.abort();
+
+    assert_eq!(message.from, 0);
+    assert_eq!(message.name, "Player");
+    assert_eq!(message.to, ChatDestination::Everyone);
+    assert_eq!(message.text, "HELLO");
+    assert!(message.is_for(None));
+}
+
+/// Has player 1 type `lines`, one character per tic, in game data packets
+/// from tic `start` on. Returns the tic after the last one.
+fn type_as_second_player(server: &StandInServer, start: u8, lines: &[&str]) -> u8 {
+    type_as_second_player_to(server, start, HU_BROADCAST, lines)
+}
+
+/// Like [`type_as_second_player`], addressing the lines with `destination`
+/// instead of to everyone.
+fn type_as_second_player_to(
+    server: &StandInServer,
+    start: u8,
+    destination: u8,
+    lines: &[&str],
+) -> u8 {
+    let mut tic = start;
+    for line in lines {
+        let typed = line.bytes().chain([KEY_ENTER]);
+        for c in [destination].into_iter().chain(typed) {
+            let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+            // seq, tic count, latency, players in game, then the diff of
+            // each player's command
+            data.extend_from_slice(&[tic, 1, 0, 0, 0x03, 0x00, 0x20, c]);
+            server.send_to_client(&data);
+            tic += 1;
+        }
+    }
+    tic
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn orders_are_followed_and_answered() {
+    let mut desc = ServerDescription::new("Chatty server");
+    desc.start_game = Some(0);
+    desc.players = Arc::new(Mutex::new(vec!["Talker".to_string(), "Alice".to_string()]));
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Talker");
+    let mut events = client.subscribe();
+    let connect_data = ConnectData::builder([0; 20]).drone(false).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+    let bot = tokio::spawn(async move { client.play(Box::new(ForwardBrain)).await });
+    while !matches!(
+        events.recv().await,
+        Some(ClientEvent::GameStarted(_)) | None
+    ) {}
+
+    let sent = tokio::task::spawn_blocking(move || {
+        let next = type_as_second_player(
+            &server,
+            0,
+            &["TALKER: HOLD", "TALKER, WEAPON SHOTGUN", "NOT FOR YOU"],
+        );
+        // Only the third player sees this one.
+        let next = type_as_second_player_to(&server, next, 3, &["TALKER: WEAPON FIST"]);
+        let mut next = type_as_second_player(&server, next, &["TALKER DANCE"]);
+
+        let mut sent: Vec<SentTic> = Vec::new();
+        let replies = |sent: &[SentTic]| {
+            sent.iter()
+                .filter(|t| t.chatchar == Some(KEY_ENTER))
+                .count()
+        };
+        while replies(&sent) < 3 {
+            let Some(data) =
+                wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5))
+            else {
+                break;
+            };
+            next = answer_tics(&server, &data, next, 0x03);
+            for tic in sent_tics(&data) {
+                if !sent.iter().any(|other| other.tic == tic.tic) {
+                    sent.push(tic);
+                }
+            }
+        }
+        sent
+    })
+    .await
+    .unwrap();
+    bot.abort();
+
+    let chat: Vec<u8> = sent.iter().filter_map(|tic| tic.chatchar).collect();
+    assert_eq!(
+        String::from_utf8_lossy(&chat),
+        "\x02HOLDING HERE\r\x02SWITCHING TO THE SHOTGUN\r\x02UNKNOWN ORDER: DANCE\r"
+    );
+
+    // The bot stops running after the first order, and picks
+    // the shotgun's slot for one tic.
+    let forward: Vec<i8> = sent.iter().filter_map(|tic| tic.forwardmove).collect();
+    assert_eq!(forward, [0x32, 0]);
+    let buttons: Vec<u8> = sent.iter().filter_map(|tic| tic.buttons).collect();
+    assert_eq!(buttons, [0x14, 0]);
+}
+
+/// Keeps the game paused until tic `until`.
+struct PausingBrain {
+    until: u32,
+}
+
+impl Brain for PausingBrain {
+    fn think(&mut self, observation: &Observation) -> TicCmd {
+        let mut cmd = TicCmd::default();
+        if observation.maketic < self.until {
+            cmd.buttons = BT_SPECIAL | BTS_PAUSE;
+        }
+        cmd
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn weapon_changes_wait_for_a_tic_without_a_special() {
+    let mut desc = ServerDescription::new("Chatty server");
+    desc.start_game = Some(0);
+    desc.players = Arc::new(Mutex::new(vec!["Talker".to_string(), "Alice".to_string()]));
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Talker");
+    let mut events = client.subscribe();
+    let connect_data = ConnectData::builder([0; 20]).drone(false).build();
+    client.connect(server.addr, connect_data).await.unwrap();
+    // The bot stays within a few tics of the server, so the order arrives
+    // well before it stops pausing.
+    let brain = PausingBrain { until: 50 };
+    let bot = tokio::spawn(async move { client.play(Box::new(brain)).await });
+    while !matches!(
+        events.recv().await,
+        Some(ClientEvent::GameStarted(_)) | None
+    ) {}
+
+    let sent = tokio::task::spawn_blocking(move || {
+        let mut next = type_as_second_player(&server, 0, &["TALKER: WEAPON SHOTGUN"]);
+
+        let mut sent: Vec<SentTic> = Vec::new();
+        while !sent.iter().any(|tic| tic.tic > 52) {
+            let Some(data) =
+                wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5))
+            else {
+                break;
+            };
+            next = answer_tics(&server, &data, next, 0x03);
+            for tic in sent_tics(&data) {
+                if !sent.iter().any(|other| other.tic == tic.tic) {
+                    sent.push(tic);
+                }
+            }
+        }
+        sent
+    })
+    .await
+    .unwrap();
+    bot.abort();
+
+    let buttons: Vec<u8> = sent.iter().filter_map(|tic| tic.buttons).collect();
+    assert_eq!(
+        buttons,
+        [BT_SPECIAL | BTS_PAUSE, BT_CHANGE | 2 << 3, 0],
+        "{:?}",
+        sent
+    );
+}
+
+/// Stands still, follows anyone and keeps a list of its orders.
+struct FollowingBrain {
+    orders: Arc<Mutex<Vec<BotCommand>>>,
+}
+
+impl Brain for FollowingBrain {
+    fn think(&mut self, _observation: &Observation) -> TicCmd {
+        TicCmd::default()
+    }
+
+    fn command(&mut self, command: &BotCommand) -> Option<String> {
+        self.orders.lock().unwrap().push(command.clone());
+        match command {
+            BotCommand::Follow(name) => Some(format!("Following {}", name)),
+            _ => None,
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn players_to_follow_are_named_as_in_the_lobby() {
+    let mut desc = ServerDescription::new("Chatty server");
+    desc.start_game = Some(0);
+    desc.players = Arc::new(Mutex::new(vec!["Talker".to_string(), "Alice".to_string()]));
+    let server = spawn_server(desc);
+
+    let mut client = new_client("Talker");
+    let mut events = client.subscribe();
+    let connect_data = ConnectData::builder([0; 20])
+        .drone(false)
+        .gamemode(GameMode::Retail)
+        .build();
+    client.connect(server.addr, connect_data).await.unwrap();
+    let orders = Arc::new(Mutex::new(Vec::new()));
+    let brain = FollowingBrain {
+        orders: orders.clone(),
+    };
+    let bot = tokio::spawn(async move { client.play(Box::new(brain)).await });
+    while !matches!(
+        events.recv().await,
+        Some(ClientEvent::GameStarted(_)) | None
+    ) {}
+
+    let sent = tokio::task::spawn_blocking(move || {
+        let mut next = type_as_second_player(
+            &server,
+            0,
+            &[
+                "TALKER: FOLLOW ALICE",
+                "TALKER: FOLLOW BOB",
+                "TALKER: STATUS",
+            ],
+        );
+
+        let mut sent: Vec<SentTic> = Vec::new();
+        while sent
+            .iter()
+            .filter(|t| t.chatchar == Some(KEY_ENTER))
+            .count()
+            < 3
+        {
+            let Some(data) =
+                wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5))
+            else {
+                break;
+            };
+            next = answer_tics(&server, &data, next, 0x03);
+            for tic in sent_tics(&data) {
+                if !sent.iter().any(|other| other.tic == tic.tic) {
+                    sent.push(tic);
+                }
+            }
+        }
+        sent
+    })
+    .await
+    .unwrap();
+    bot.abort();
+
+    // Nobody called Bob is around, so the name stays as typed.
+    assert_eq!(
+        *orders.lock().unwrap(),
+        [
+            BotCommand::Follow("Alice".to_string()),
+            BotCommand::Follow("BOB".to_string()),
+            BotCommand::Status,
+        ]
+    );
+    // Games with episodes say which one they are on.
+    let chat: Vec<u8> = sent.iter().filter_map(|tic| tic.chatchar).collect();
+    assert_eq!(
+        String::from_utf8_lossy(&chat),
+        "\x02FOLLOWING ALICE\r\x02FOLLOWING BOB\r\x02E1M1: ON THE MOVE\r"
+    );
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hydra_bot::net::{ClientEvent, ClientState, GameSettings, TicCmd};
+
+use common::*;
+
+#[test]
+fn acknowledged_tics_are_not_sent_again() {
+    let mut desc = ServerDescription::new("Acking server");
+    desc.start_game = Some(0);
+    desc.extratics = 2;
+    let (server, mut client, _clock) = join_virtual(desc, false);
+
+    let cmd = TicCmd::default();
+    client.send_ticcmd(&cmd, 0);
+    client.send_ticcmd(&cmd, 1);
+    server.send_to_client(&game_data_ack(2));
+    server.sync(&mut client);
+    client.send_ticcmd(&cmd, 2);
+
+    // The server has tics 0 and 1, so they are neither sent with the extra
+    // tics nor resent on request.
+    let mut request = PACKET_TYPE_GAME_DATA_RESEND.to_be_bytes().to_vec();
+    request.extend_from_slice(&0i32.to_be_bytes());
+    request.push(2);
+    server.send_to_client(&request);
+    server.sync(&mut client);
+
+    let sent: Vec<_> = server
+        .received_of(PACKET_TYPE_GAME_DATA)
+        .iter()
+        .map(|data| first_tic(data))
+        .map(|(start, count, _)| (start, count))
+        .collect();
+    assert_eq!(sent, [(0, 1), (0, 2), (2, 1)]);
+}
+
+#[test]
+fn round_trip_time_is_smoothed() {
+    let mut desc = ServerDescription::new("Acking server");
+    desc.start_game = Some(0);
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let cmd = TicCmd::default();
+    client.send_ticcmd(&cmd, 0);
+    clock.advance(Duration::from_millis(100));
+    server.send_to_client(&game_data_ack(1));
+    server.sync(&mut client);
+
+    client.send_ticcmd(&cmd, 1);
+    clock.advance(Duration::from_millis(20));
+    server.send_to_client(&game_data_ack(2));
+    server.sync(&mut client);
+    // Repeated acks measure nothing.
+    clock.advance(Duration::from_millis(500));
+    server.send_to_client(&game_data_ack(2));
+    server.sync(&mut client);
+
+    client.send_ticcmd(&cmd, 2);
+
+    let latencies: Vec<_> = server
+        .received_of(PACKET_TYPE_GAME_DATA)
+        .iter()
+        .map(|data| first_latency(data))
+        .collect();
+    // The first sample is taken as is, later ones move the estimate an
+    // eighth of the way.
+    assert_eq!(latencies, [0, 100, 90]);
+}
+
+#[test]
+fn long_round_trip_times_are_capped() {
+    let mut desc = ServerDescription::new("Stalled server");
+    desc.start_game = Some(0);
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let cmd = TicCmd::default();
+    client.send_ticcmd(&cmd, 0);
+    clock.advance(Duration::from_secs(40));
+    server.send_to_client(&game_data_ack(1));
+    server.sync(&mut client);
+    client.send_ticcmd(&cmd, 1);
+
+    let latencies: Vec<_> = server
+        .received_of(PACKET_TYPE_GAME_DATA)
+        .iter()
+        .map(|data| first_latency(data))
+        .collect();
+    assert_eq!(latencies, [0, i16::MAX]);
+}
+
+#[test]
+fn clock_offset_is_bounded_and_reset_by_a_new_game() {
+    let mut desc = ServerDescription::new("Drifting server");
+    desc.start_game = Some(0);
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    // Each tic's latency runs from sending ours to receiving the server's,
+    // so matching the server's leaves the clock alone.
+    let cmd = TicCmd::default();
+    client.send_ticcmd(&cmd, 0);
+    clock.advance(Duration::from_millis(100));
+    server.send_to_client(&empty_tics(0, 1, 100));
+    server.sync(&mut client);
+    assert_eq!(client.get_offset_ms(), 0);
+
+    client.send_ticcmd(&cmd, 1);
+    clock.advance(Duration::from_millis(100));
+    server.send_to_client(&empty_tics(1, 1, -30000));
+    server.sync(&mut client);
+    assert_eq!(client.get_offset_ms(), 1000);
+
+    // A tic we have not sent yet counts as no latency at all.
+    server.send_to_client(&empty_tics(2, 1, 30000));
+    server.sync(&mut client);
+    assert_eq!(client.get_offset_ms(), -1000);
+
+    // The next level starts from scratch. Its game start is the second
+    // reliable packet.
+    let mut game_start = game_start(&ServerDescription::new("Drifting server"), 0);
+    game_start[2] = 2;
+    server.send_to_client(&game_start);
+    server.sync(&mut client);
+    assert_eq!(client.get_offset_ms(), 0);
+}
+
+#[test]
+fn keepalives_are_sent_every_second() {
+    let (server, mut client, clock) = join_virtual(ServerDescription::new("Quiet server"), true);
+
+    let mut keepalives = Vec::new();
+    for millis in [1001, 1000, 1, 500] {
+        clock.advance(Duration::from_millis(millis));
+        client.run();
+        keepalives.push(server.received_of(PACKET_TYPE_KEEPALIVE).len());
+    }
+    assert_eq!(keepalives, [1, 0, 1, 0]);
+}
+
+#[test]
+fn reliable_packets_are_resent_until_acknowledged() {
+    let mut desc = ServerDescription::new("Forgetful server");
+    desc.controller = true;
+    desc.ignore_first_launch = true;
+    desc.players = Arc::new(Mutex::new(vec!["Virtual".to_string()]));
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    client.start_game(GameSettings::builder().build()).unwrap();
+
+    // The server ignored the first launch, so only the resend gets through.
+    clock.advance(Duration::from_millis(999));
+    client.run();
+    assert!(server.received_of(PACKET_TYPE_LAUNCH).is_empty());
+    clock.advance(Duration::from_millis(1));
+    client.run();
+    assert_eq!(server.received_of(PACKET_TYPE_LAUNCH).len(), 1);
+
+    // Once acknowledged, the game start goes out without waiting.
+    server.sync(&mut client);
+    assert_eq!(server.received_of(PACKET_TYPE_GAME_START).len(), 1);
+}
+
+#[test]
+fn launches_for_too_many_players_are_ignored() {
+    let mut desc = ServerDescription::new("Crowded server");
+    desc.controller = true;
+    desc.launch_players = Some(200);
+    desc.players = Arc::new(Mutex::new(vec!["Virtual".to_string()]));
+    let (server, mut client, _clock) = join_virtual(desc, false);
+
+    client.start_game(GameSettings::builder().build()).unwrap();
+    server.sync(&mut client);
+
+    // The launch is dropped, so no game start goes out and the client keeps
+    // waiting in the lobby.
+    assert!(server.received_of(PACKET_TYPE_GAME_START).is_empty());
+    assert_eq!(client.get_state(), ClientState::WaitingLaunch);
+    assert_eq!(client.get_lobby().unwrap().players.len(), 1);
+    server.sync(&mut client);
+}
+
+#[test]
+fn resend_requests_without_queued_tics_are_ignored() {
+    let mut desc = ServerDescription::new("Confused server");
+    desc.start_game = Some(0);
+    let (server, mut client, _clock) = join_virtual(desc, false);
+
+    client.send_ticcmd(&TicCmd::default(), 0);
+    server.send_to_client(&game_data_ack(1));
+    server.sync(&mut client);
+    server.received();
+
+    // Requests for no tics, for tics long acknowledged and for tics far
+    // ahead all find nothing to resend.
+    for (start, num_tics) in [(0, 0), (0, 1), (i32::MAX, 255), (-300, 255)] {
+        let mut request = PACKET_TYPE_GAME_DATA_RESEND.to_be_bytes().to_vec();
+        request.extend_from_slice(&start.to_be_bytes());
+        request.push(num_tics);
+        server.send_to_client(&request);
+    }
+    server.sync(&mut client);
+    assert!(server.received_of(PACKET_TYPE_GAME_DATA).is_empty());
+
+    client.send_ticcmd(&TicCmd::default(), 1);
+    assert_eq!(server.received_of(PACKET_TYPE_GAME_DATA).len(), 1);
+}
+
+#[test]
+fn game_data_beyond_the_receive_window_is_not_read() {
+    let mut desc = ServerDescription::new("Hasty server");
+    desc.start_game = Some(0);
+    let (server, mut client, _clock) = join_virtual(desc, false);
+
+    // Tic 160 is past the window, and so is everything it leaves missing.
+    server.send_to_client(&empty_tics(160, 0, 0));
+    server.sync(&mut client);
+    assert!(client.is_connected());
+}
+
+#[test]
+fn game_data_is_ignored_before_the_game() {
+    let (server, mut client, _clock) = join_virtual(ServerDescription::new("Early server"), false);
+
+    server.send_to_client(&empty_tics(160, 0, 0));
+    server.sync(&mut client);
+    assert_eq!(client.get_state(), ClientState::WaitingLaunch);
+    assert!(server.received_of(PACKET_TYPE_GAME_DATA_ACK).is_empty());
+}
+
+#[test]
+fn packets_from_anyone_but_the_server_are_ignored() {
+    let (server, mut client, _clock) = join_virtual(ServerDescription::new("Busy server"), false);
+
+    let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let client_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), client.get_local_addr().port());
+    stranger
+        .send_to(&console_message("Not the server"), client_addr)
+        .unwrap();
+    server.send_to_client(&console_message("The server"));
+
+    let mut messages = Vec::new();
+    while messages.last().map(String::as_str) != Some("The server") {
+        client.run();
+        while let Some(event) = client.next_event() {
+            if let ClientEvent::ConsoleMessage(message) = event {
+                messages.push(message.text);
+            }
+        }
+    }
+    assert_eq!(messages, ["The server"]);
+}
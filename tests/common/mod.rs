@@ -0,0 +1,666 @@
+#![allow(dead_code)]
+
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use hydra_bot::clock::VirtualClock;
+use hydra_bot::net::{ClientEvent, ConnectData};
+use hydra_bot::{AsyncClient, Client, ClientConfig};
+use sha1::{Digest, Sha1};
+
+pub const NET_RELIABLE_PACKET: u16 = 1 << 15;
+pub const PACKET_TYPE_SYN: u16 = 0;
+pub const PACKET_TYPE_REJECTED: u16 = 2;
+pub const PACKET_TYPE_KEEPALIVE: u16 = 3;
+pub const PACKET_TYPE_WAITING_DATA: u16 = 4;
+pub const PACKET_TYPE_GAME_START: u16 = 5;
+pub const PACKET_TYPE_GAME_DATA: u16 = 6;
+pub const PACKET_TYPE_GAME_DATA_ACK: u16 = 7;
+pub const PACKET_TYPE_DISCONNECT: u16 = 8;
+pub const PACKET_TYPE_DISCONNECT_ACK: u16 = 9;
+pub const PACKET_TYPE_RELIABLE_ACK: u16 = 10;
+pub const PACKET_TYPE_GAME_DATA_RESEND: u16 = 11;
+pub const PACKET_TYPE_CONSOLE_MESSAGE: u16 = 12;
+pub const PACKET_TYPE_QUERY: u16 = 13;
+pub const PACKET_TYPE_QUERY_RESPONSE: u16 = 14;
+pub const PACKET_TYPE_LAUNCH: u16 = 15;
+pub const PACKET_TYPE_NAT_HOLE_PUNCH: u16 = 16;
+// Not a real packet type, used to tell where the packets sent so far end.
+const PACKET_TYPE_MARKER: u16 = 0x7fff;
+pub const MASTER_PACKET_TYPE_QUERY: u16 = 2;
+pub const MASTER_PACKET_TYPE_QUERY_RESPONSE: u16 = 3;
+pub const MASTER_PACKET_TYPE_NAT_HOLE_PUNCH: u16 = 10;
+
+pub const WAD_CONTENTS: &[u8] = b"IWAD\0\0\0\0\0\0\0\0";
+
+/// What a stand-in server answers to queries with.
+#[derive(Clone)]
+pub struct ServerDescription {
+    pub description: String,
+    pub server_state: u8,
+    pub num_players: u8,
+    pub max_players: u8,
+    pub gamemode: u8,
+    pub gamemission: u8,
+    pub wad_sha1sum: [u8; 20],
+    /// Starts the game as soon as a client connects, giving it this player
+    /// slot (-1 for drones).
+    pub start_game: Option<i8>,
+    /// The ticdup and extratics of the game started for clients.
+    pub ticdup: u8,
+    pub extratics: u8,
+    /// Turns every client away with this reason.
+    pub reject: Option<String>,
+    /// The names listed in the lobby. Changes show up in the next lobby
+    /// data sent.
+    pub players: Arc<Mutex<Vec<String>>>,
+    /// Makes clients the lobby controller, in player slot 0. The game starts
+    /// with whatever settings they send.
+    pub controller: bool,
+    /// Ignores the first launch request, to make the client send it again.
+    pub ignore_first_launch: bool,
+    /// Launches games for this many players instead of the lobby's.
+    pub launch_players: Option<u8>,
+    /// Greets every client with this on the console.
+    pub console_message: Option<String>,
+    /// Ignores the first disconnect, to make the client send it again.
+    pub ignore_first_disconnect: bool,
+}
+
+impl ServerDescription {
+    pub fn new(description: &str) -> Self {
+        ServerDescription {
+            description: description.to_string(),
+            server_state: 0,
+            num_players: 1,
+            max_players: 4,
+            gamemode: 2,
+            gamemission: 0,
+            wad_sha1sum: Sha1::digest(WAD_CONTENTS).into(),
+            start_game: None,
+            ticdup: 1,
+            extratics: 0,
+            reject: None,
+            players: Arc::new(Mutex::new(vec!["Player".to_string()])),
+            controller: false,
+            ignore_first_launch: false,
+            launch_players: None,
+            console_message: None,
+            ignore_first_disconnect: false,
+        }
+    }
+}
+
+/// A local stand-in for a Chocolate Doom server. It answers queries, accepts
+/// connections into its lobby and reports the type of every other packet it
+/// receives.
+pub struct StandInServer {
+    pub addr: SocketAddr,
+    pub packets: Receiver<(u16, Vec<u8>)>,
+    socket: UdpSocket,
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+}
+
+impl StandInServer {
+    fn new(
+        socket: &UdpSocket,
+        packets: Receiver<(u16, Vec<u8>)>,
+    ) -> (Self, Arc<Mutex<Option<SocketAddr>>>) {
+        let peer = Arc::new(Mutex::new(None));
+        let server = StandInServer {
+            addr: socket.local_addr().unwrap(),
+            packets,
+            socket: socket.try_clone().unwrap(),
+            peer: peer.clone(),
+        };
+        (server, peer)
+    }
+
+    /// Sends a packet to the last client that connected.
+    pub fn send_to_client(&self, data: &[u8]) {
+        let peer = self.peer.lock().unwrap().expect("no client connected");
+        self.socket.send_to(data, peer).unwrap();
+    }
+
+    /// Where the last client that connected sent from.
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        *self.peer.lock().unwrap()
+    }
+
+    /// Takes every packet that reached the server so far, in order.
+    pub fn received(&self) -> Vec<(u16, Vec<u8>)> {
+        let marker = UdpSocket::bind(SocketAddr::new(self.addr.ip(), 0)).unwrap();
+        marker
+            .send_to(&PACKET_TYPE_MARKER.to_be_bytes(), self.addr)
+            .unwrap();
+
+        let mut packets = Vec::new();
+        loop {
+            match self.packets.recv_timeout(Duration::from_secs(5)) {
+                Ok((PACKET_TYPE_MARKER, _)) => return packets,
+                Ok(packet) => packets.push(packet),
+                Err(_) => panic!("server stopped receiving"),
+            }
+        }
+    }
+
+    /// Takes every packet of the given type that reached the server so far.
+    pub fn received_of(&self, packet_type: u16) -> Vec<Vec<u8>> {
+        self.received()
+            .into_iter()
+            .filter(|(t, _)| *t == packet_type)
+            .map(|(_, data)| data)
+            .collect()
+    }
+
+    /// Runs `client` until it has handled everything the server sent it so
+    /// far. Events are taken from its queue on the way.
+    pub fn sync(&self, client: &mut Client) {
+        self.send_to_client(&console_message("sync"));
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            client.run();
+            while let Some(event) = client.next_event() {
+                if matches!(&event, ClientEvent::ConsoleMessage(m) if m.text == "sync") {
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("client did not catch up with the server");
+    }
+}
+
+/// Joins a stand-in server with a client on a virtual clock, so that the
+/// test decides when time passes. Returns once the client has handled the
+/// server's greeting, and the game start if the server sends one.
+pub fn join_virtual(
+    desc: ServerDescription,
+    drone: bool,
+) -> (StandInServer, Client, Arc<VirtualClock>) {
+    let server = spawn_server(desc);
+    let clock = Arc::new(VirtualClock::new());
+    // Time only passes while connecting, so the server always answers in
+    // time.
+    let config = ClientConfig::builder()
+        .player_name("Virtual")
+        .connection_timeout(Duration::from_secs(24 * 60 * 60))
+        .max_retries(u32::MAX)
+        .build();
+    let mut client = Client::new(config, clock.clone()).unwrap();
+    client.init();
+
+    let connect_data = ConnectData::builder([0; 20]).drone(drone).build();
+    client.connect(server.addr, connect_data).unwrap();
+    server.sync(&mut client);
+    server.received();
+
+    (server, client, clock)
+}
+
+/// An async client going by `name`, with the default configuration.
+pub fn new_client(name: &str) -> AsyncClient {
+    let config = ClientConfig::builder().player_name(name).build();
+    AsyncClient::new(config).unwrap()
+}
+
+pub fn spawn_server(desc: ServerDescription) -> StandInServer {
+    spawn_server_on("127.0.0.1:0", desc)
+}
+
+pub fn spawn_server_on(addr: &str, desc: ServerDescription) -> StandInServer {
+    let socket = UdpSocket::bind(addr).unwrap();
+    let (tx, packets) = mpsc::channel();
+    let (server, peer) = StandInServer::new(&socket, packets);
+
+    thread::spawn(move || serve(socket, desc, tx, peer, None));
+
+    server
+}
+
+/// Spawns a stand-in server behind a simulated NAT together with the master
+/// server relaying hole punch requests to it. The server drops everything
+/// from peers it has not punched a hole towards yet.
+pub fn spawn_nat_server(desc: ServerDescription) -> (StandInServer, SocketAddr) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let master = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let master_addr = master.local_addr().unwrap();
+    let (tx, packets) = mpsc::channel();
+    let (server, peer) = StandInServer::new(&socket, packets);
+
+    thread::spawn(move || serve(socket, desc, tx, peer, Some(master_addr)));
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok((size, from)) = master.recv_from(&mut buf) {
+            if size < 2
+                || u16::from_be_bytes([buf[0], buf[1]]) != MASTER_PACKET_TYPE_NAT_HOLE_PUNCH
+                || read_string(&buf[2..size]) != addr.to_string()
+            {
+                continue;
+            }
+
+            let mut data = MASTER_PACKET_TYPE_NAT_HOLE_PUNCH.to_be_bytes().to_vec();
+            write_string(&mut data, &from.to_string());
+            master.send_to(&data, addr).unwrap();
+        }
+    });
+
+    (server, master_addr)
+}
+
+fn serve(
+    socket: UdpSocket,
+    desc: ServerDescription,
+    tx: Sender<(u16, Vec<u8>)>,
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+    master: Option<SocketAddr>,
+) {
+    let mut punched: Vec<SocketAddr> = Vec::new();
+    let mut ignore_launch = desc.ignore_first_launch;
+    let mut ignore_disconnect = desc.ignore_first_disconnect;
+    let mut buf = [0u8; 4096];
+
+    while let Ok((size, from)) = socket.recv_from(&mut buf) {
+        if size < 2 {
+            continue;
+        }
+
+        let packet_type = u16::from_be_bytes([buf[0], buf[1]]);
+
+        if let Some(master) = master {
+            if from == master {
+                if packet_type == MASTER_PACKET_TYPE_NAT_HOLE_PUNCH {
+                    let peer: SocketAddr = read_string(&buf[2..size]).parse().unwrap();
+                    punched.push(peer);
+                    socket
+                        .send_to(&PACKET_TYPE_NAT_HOLE_PUNCH.to_be_bytes(), peer)
+                        .unwrap();
+                }
+                continue;
+            }
+
+            if !punched.contains(&from) {
+                continue;
+            }
+        }
+
+        let mut packet_type = packet_type;
+        let mut body = &buf[2..size];
+        if packet_type & NET_RELIABLE_PACKET != 0 {
+            if packet_type == NET_RELIABLE_PACKET | PACKET_TYPE_LAUNCH && ignore_launch {
+                ignore_launch = false;
+                continue;
+            }
+
+            let mut ack = PACKET_TYPE_RELIABLE_ACK.to_be_bytes().to_vec();
+            ack.push(body[0].wrapping_add(1));
+            socket.send_to(&ack, from).unwrap();
+            packet_type &= !NET_RELIABLE_PACKET;
+            body = &body[1..];
+        }
+
+        if packet_type == PACKET_TYPE_QUERY {
+            socket.send_to(&query_response(&desc), from).unwrap();
+            continue;
+        } else if packet_type == PACKET_TYPE_LAUNCH && desc.controller {
+            let num_players = desc
+                .launch_players
+                .unwrap_or(desc.players.lock().unwrap().len() as u8);
+            let mut data = (NET_RELIABLE_PACKET | PACKET_TYPE_LAUNCH)
+                .to_be_bytes()
+                .to_vec();
+            data.extend_from_slice(&[1, num_players]);
+            socket.send_to(&data, from).unwrap();
+        } else if packet_type == PACKET_TYPE_GAME_START && desc.controller {
+            // Follows the launch, so it is the second reliable packet.
+            let mut data = (NET_RELIABLE_PACKET | PACKET_TYPE_GAME_START)
+                .to_be_bytes()
+                .to_vec();
+            data.push(2);
+            data.extend_from_slice(body);
+            socket.send_to(&data, from).unwrap();
+        } else if packet_type == PACKET_TYPE_SYN && desc.reject.is_some() {
+            let mut data = PACKET_TYPE_REJECTED.to_be_bytes().to_vec();
+            write_string(&mut data, desc.reject.as_deref().unwrap());
+            socket.send_to(&data, from).unwrap();
+        } else if packet_type == PACKET_TYPE_SYN {
+            *peer.lock().unwrap() = Some(from);
+            socket.send_to(&syn_response(), from).unwrap();
+            socket.send_to(&waiting_data(&desc), from).unwrap();
+            if let Some(text) = &desc.console_message {
+                socket.send_to(&console_message(text), from).unwrap();
+            }
+            if let Some(consoleplayer) = desc.start_game {
+                socket
+                    .send_to(&game_start(&desc, consoleplayer), from)
+                    .unwrap();
+            }
+        } else if packet_type == PACKET_TYPE_DISCONNECT && ignore_disconnect {
+            ignore_disconnect = false;
+        } else if packet_type == PACKET_TYPE_DISCONNECT {
+            socket
+                .send_to(&PACKET_TYPE_DISCONNECT_ACK.to_be_bytes(), from)
+                .unwrap();
+        } else if packet_type == PACKET_TYPE_KEEPALIVE {
+            socket.send_to(&waiting_data(&desc), from).unwrap();
+        }
+
+        if tx.send((packet_type, body.to_vec())).is_err() {
+            break;
+        }
+    }
+}
+
+pub fn query_response(desc: &ServerDescription) -> Vec<u8> {
+    let mut data = PACKET_TYPE_QUERY_RESPONSE.to_be_bytes().to_vec();
+    write_string(&mut data, "Chocolate Doom 3.0.1");
+    data.extend_from_slice(&[
+        desc.server_state,
+        desc.num_players,
+        desc.max_players,
+        desc.gamemode,
+        desc.gamemission,
+    ]);
+    write_string(&mut data, &desc.description);
+    data.push(1);
+    write_string(&mut data, "CHOCOLATE_DOOM_0");
+    data
+}
+
+pub fn console_message(text: &str) -> Vec<u8> {
+    let mut data = PACKET_TYPE_CONSOLE_MESSAGE.to_be_bytes().to_vec();
+    write_string(&mut data, text);
+    data
+}
+
+pub fn syn_response() -> Vec<u8> {
+    let mut data = (NET_RELIABLE_PACKET | PACKET_TYPE_SYN)
+        .to_be_bytes()
+        .to_vec();
+    data.push(0);
+    write_string(&mut data, "Chocolate Doom 3.0.1");
+    write_string(&mut data, "CHOCOLATE_DOOM_0");
+    data
+}
+
+pub fn waiting_data(desc: &ServerDescription) -> Vec<u8> {
+    let mut data = PACKET_TYPE_WAITING_DATA.to_be_bytes().to_vec();
+    let players = desc.players.lock().unwrap().clone();
+    // Servers that start the game know our slot from the lobby on.
+    let (is_controller, consoleplayer) = match desc.controller {
+        true => (1, 0),
+        false => (0, desc.start_game.map_or(0xff, |slot| slot as u8)),
+    };
+    // Everyone in the lobby counts as ready.
+    data.extend_from_slice(&[
+        players.len() as u8,
+        0,
+        players.len() as u8,
+        desc.max_players,
+        is_controller,
+        consoleplayer,
+    ]);
+    for name in &players {
+        write_string(&mut data, name);
+        write_string(&mut data, "127.0.0.1");
+    }
+    data.extend_from_slice(&desc.wad_sha1sum);
+    data.extend_from_slice(&[0; 20]);
+    data.push(0);
+    data
+}
+
+/// Reliable game start packet for a game of the server's players, as the
+/// first reliable packet after the SYN response.
+pub fn game_start(desc: &ServerDescription, consoleplayer: i8) -> Vec<u8> {
+    let mut data = (NET_RELIABLE_PACKET | PACKET_TYPE_GAME_START)
+        .to_be_bytes()
+        .to_vec();
+    data.push(1);
+    // ticdup, extratics, deathmatch, nomonsters, fast, respawn, episode, map,
+    // skill, gameversion, lowres_turn, new_sync
+    data.extend_from_slice(&[desc.ticdup, desc.extratics, 0, 0, 0, 0, 1, 1, 2, 0, 0, 1]);
+    data.extend_from_slice(&0u32.to_be_bytes());
+    // loadgame, random, num_players, consoleplayer, then the player classes
+    data.extend_from_slice(&[0xff, 0, desc.num_players, consoleplayer as u8]);
+    data.resize(data.len() + desc.num_players as usize, 0);
+    data
+}
+
+/// Game data from the server for `count` tics from `start` on, with no
+/// player in the game and `latency` as measured by the server.
+pub fn empty_tics(start: u8, count: u8, latency: i16) -> Vec<u8> {
+    let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+    data.extend_from_slice(&[start, count]);
+    for _ in 0..count {
+        data.extend_from_slice(&latency.to_be_bytes());
+        data.push(0);
+    }
+    data
+}
+
+/// Plays the server's part in a game, answering the game data a client sent
+/// with the tics from `next` up to its last one, where the players in
+/// `ingame` stand still. Returns the tic after the last one answered.
+pub fn answer_tics(server: &StandInServer, sent: &[u8], next: u8, ingame: u8) -> u8 {
+    let end = sent[1] + sent[2];
+    if end <= next {
+        return next;
+    }
+
+    let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+    data.extend_from_slice(&[next, end - next]);
+    for _ in next..end {
+        // latency, players in game, then the diff of each player's command
+        data.extend_from_slice(&[0, 0, ingame]);
+        data.resize(data.len() + ingame.count_ones() as usize, 0);
+    }
+    server.send_to_client(&data);
+    end
+}
+
+/// The server's acknowledgement of every tic before `next`.
+pub fn game_data_ack(next: u8) -> Vec<u8> {
+    let mut data = PACKET_TYPE_GAME_DATA_ACK.to_be_bytes().to_vec();
+    data.push(next);
+    data
+}
+
+/// A local stand-in for the master server, listing the given addresses.
+/// The list is split over several packets like the real one does for long
+/// lists.
+pub fn spawn_master(servers: Vec<SocketAddr>) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok((size, from)) = socket.recv_from(&mut buf) {
+            if size < 2 || u16::from_be_bytes([buf[0], buf[1]]) != MASTER_PACKET_TYPE_QUERY {
+                continue;
+            }
+
+            for chunk in servers.chunks(2) {
+                let mut data = MASTER_PACKET_TYPE_QUERY_RESPONSE.to_be_bytes().to_vec();
+                for server in chunk {
+                    write_string(&mut data, &server.to_string());
+                }
+                socket.send_to(&data, from).unwrap();
+            }
+        }
+    });
+
+    addr
+}
+
+/// The parts of a client's SYN packet the tests look at.
+#[derive(Debug)]
+pub struct Syn {
+    pub description: String,
+    pub lowres_turn: u8,
+    pub drone: u8,
+    pub max_players: u8,
+    pub player_class: u8,
+    pub name: String,
+}
+
+pub fn parse_syn(data: &[u8]) -> Syn {
+    // Skip the magic number.
+    let mut pos = 4;
+    let description = read_string(&data[pos..]);
+    pos += description.len() + 1;
+
+    let num_protocols = data[pos];
+    pos += 1;
+    for _ in 0..num_protocols {
+        pos += read_string(&data[pos..]).len() + 1;
+    }
+
+    let connect_data = &data[pos..pos + 47];
+    let name = read_string(&data[pos + 47..]);
+
+    Syn {
+        description,
+        lowres_turn: connect_data[2],
+        drone: connect_data[3],
+        max_players: connect_data[4],
+        player_class: connect_data[46],
+        name,
+    }
+}
+
+pub fn read_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+pub fn write_string(data: &mut Vec<u8>, s: &str) {
+    data.extend_from_slice(s.as_bytes());
+    data.push(0);
+}
+
+/// Writes a throwaway WAD file so the binary has something to hash.
+pub fn dummy_wad(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("hydra-bot-{}-{}.wad", name, std::process::id()));
+    std::fs::write(&path, WAD_CONTENTS).unwrap();
+    path
+}
+
+pub fn bot(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_hydra-bot"));
+    command.args(args).env("RUST_LOG", "info");
+    command
+}
+
+pub fn run_bot(args: &[&str]) -> Output {
+    bot(args).output().unwrap()
+}
+
+pub fn spawn_bot(args: &[&str]) -> Child {
+    bot(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+/// Waits for a bot to exit on its own, killing it if it takes longer than
+/// `timeout`. Returns how it exited, if it did by itself.
+pub fn wait_for_exit(bot: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = std::time::Instant::now() + timeout;
+    while std::time::Instant::now() < deadline {
+        if let Some(status) = bot.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+    None
+}
+
+/// Reads the first tic of a game data packet sent by the client, returning
+/// its start tic, tic count and forward move.
+pub fn first_tic(data: &[u8]) -> (u8, u8, Option<i8>) {
+    let (start, count) = (data[1], data[2]);
+    // Skip the latency of the first tic.
+    let diff = data[5];
+    let forwardmove = (diff & 1 != 0).then(|| data[6] as i8);
+    (start, count, forwardmove)
+}
+
+/// The latency the client reported with the first tic of a game data
+/// packet.
+pub fn first_latency(data: &[u8]) -> i16 {
+    i16::from_be_bytes([data[3], data[4]])
+}
+
+/// The parts of a command sent by the client that changed since its last
+/// one, besides the chat character, which is only sent when there is one.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SentTic {
+    pub tic: u8,
+    pub forwardmove: Option<i8>,
+    pub buttons: Option<u8>,
+    pub chatchar: Option<u8>,
+}
+
+/// Reads every tic of a game data packet sent by the client, which must use
+/// full resolution turning.
+pub fn sent_tics(data: &[u8]) -> Vec<SentTic> {
+    let (start, count) = (data[1], data[2]);
+    let mut pos = 3;
+    let mut tics = Vec::new();
+    for i in 0..count {
+        // Skip the latency.
+        let diff = data[pos + 2];
+        pos += 3;
+        assert_eq!(diff & !0x3f, 0, "unexpected game specific fields");
+
+        let mut field = |bit: u8, size: usize| {
+            (diff & bit != 0).then(|| {
+                pos += size;
+                data[pos - size]
+            })
+        };
+        tics.push(SentTic {
+            tic: start.wrapping_add(i),
+            forwardmove: field(0x01, 1).map(|b| b as i8),
+            buttons: {
+                field(0x02, 1);
+                field(0x04, 2);
+                field(0x08, 1)
+            },
+            chatchar: {
+                field(0x10, 1);
+                field(0x20, 1)
+            },
+        });
+    }
+    tics
+}
+
+/// Waits for a packet of the given type to reach the stand-in server.
+pub fn wait_for_packet(
+    server: &StandInServer,
+    packet_type: u16,
+    timeout: Duration,
+) -> Option<Vec<u8>> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let remaining = deadline.checked_duration_since(std::time::Instant::now())?;
+        match server.packets.recv_timeout(remaining) {
+            Ok((t, data)) if t == packet_type => return Some(data),
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
+    }
+}
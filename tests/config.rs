@@ -0,0 +1,147 @@
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+
+fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("hydra-bot-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn identity_comes_from_the_command_line() {
+    let server = spawn_server(ServerDescription::new("Config server"));
+    let wad = dummy_wad("cli-identity");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--name",
+        "Marine",
+        "--player-class",
+        "2",
+        "--max-players",
+        "4",
+        "--lowres-turn",
+        "true",
+        "--game-description",
+        "Chocolate Doom 3.1.0",
+    ]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    let syn = parse_syn(&syn.expect("bot did not connect"));
+    assert_eq!(syn.name, "Marine");
+    assert_eq!(syn.player_class, 2);
+    assert_eq!(syn.max_players, 4);
+    assert_eq!(syn.lowres_turn, 1);
+    assert_eq!(syn.description, "Chocolate Doom 3.1.0");
+}
+
+#[test]
+fn config_file_fills_in_for_the_command_line() {
+    let server = spawn_server(ServerDescription::new("Config server"));
+    let wad = dummy_wad("file-identity");
+    let config = write_config(
+        "identity",
+        "name = \"FileBot\"\nmax-players = 4\nlowres-turn = true\n\
+         game-description = \"Chocolate Doom 3.1.0\"\n",
+    );
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--config",
+        config.to_str().unwrap(),
+        "--max-players",
+        "6",
+        "--lowres-turn",
+        "false",
+    ]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    let syn = parse_syn(&syn.expect("bot did not connect"));
+    assert_eq!(syn.name, "FileBot");
+    assert_eq!(syn.max_players, 6);
+    assert_eq!(syn.lowres_turn, 0);
+    assert_eq!(syn.description, "Chocolate Doom 3.1.0");
+}
+
+#[test]
+fn retries_are_configurable() {
+    // Nothing answers on this socket, so every SYN goes unanswered.
+    let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let wad = dummy_wad("retries");
+    let config = write_config("retries", "max-retries = 2\njoin-retries = 0\n");
+
+    let address = silent.local_addr().unwrap().to_string();
+    let output = run_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--config",
+        config.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("after 2 retries"), "{}", stdout);
+    assert!(!stdout.contains("Retrying connection"), "{}", stdout);
+}
+
+#[test]
+fn unknown_config_keys_are_rejected() {
+    let wad = dummy_wad("bad-config");
+    let config = write_config("bad", "nmae = \"Typo\"\n");
+
+    let output = run_bot(&[
+        "-a",
+        "127.0.0.1:2342",
+        "-i",
+        wad.to_str().unwrap(),
+        "--config",
+        config.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("nmae"), "{}", stderr);
+}
+
+#[test]
+fn invalid_game_settings_are_rejected() {
+    let wad = dummy_wad("bad-settings");
+
+    for (option, value, error) in [
+        ("--ticdup", "0", "Invalid ticdup 0"),
+        ("--extratics", "-1", "Invalid extratics -1"),
+        ("--deathmatch", "4", "Invalid deathmatch 4"),
+    ] {
+        let output = run_bot(&[
+            "-a",
+            "127.0.0.1:2342",
+            "-i",
+            wad.to_str().unwrap(),
+            option,
+            value,
+        ]);
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{}", stderr);
+    }
+}
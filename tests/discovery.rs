@@ -0,0 +1,72 @@
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+
+#[test]
+fn local_search_lists_stand_in_server() {
+    let server = spawn_server(ServerDescription::new("Stand-in LAN server"));
+    let wad = dummy_wad("search");
+    let search_address = server.addr.to_string();
+
+    let output = run_bot(&[
+        "--local-search",
+        "--search-address",
+        &search_address,
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "bot failed: {:?}", output);
+    assert!(stdout.contains("Stand-in LAN server"), "{}", stdout);
+    assert!(stdout.contains(&search_address), "{}", stdout);
+    assert!(stdout.contains("1/4"), "{}", stdout);
+}
+
+#[test]
+fn auto_join_connects_to_joinable_server() {
+    let server = spawn_server(ServerDescription::new("Joinable server"));
+    let wad = dummy_wad("auto-join");
+    let search_address = server.addr.to_string();
+
+    let mut bot = spawn_bot(&[
+        "--local-search",
+        "--auto-join",
+        "--search-address",
+        &search_address,
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(
+        syn.is_some(),
+        "bot never sent a SYN to the discovered server"
+    );
+}
+
+#[test]
+fn auto_join_skips_full_servers() {
+    let mut desc = ServerDescription::new("Full server");
+    desc.num_players = 4;
+    let server = spawn_server(desc);
+    let wad = dummy_wad("full");
+    let search_address = server.addr.to_string();
+
+    let output = run_bot(&[
+        "--local-search",
+        "--auto-join",
+        "--search-address",
+        &search_address,
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(server.packets.try_recv().is_err());
+}
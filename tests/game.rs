@@ -0,0 +1,322 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hydra_bot::brain::{Brain, ForwardBrain, Observation};
+use hydra_bot::net::{TicCmd, NET_MAXPLAYERS};
+use hydra_bot::Game;
+
+use common::*;
+
+/// What a brain was shown for one tic.
+#[derive(Debug, Clone)]
+struct Seen {
+    map: i32,
+    maketic: u32,
+    gametic: u32,
+    cmds: [TicCmd; NET_MAXPLAYERS],
+    console: Vec<String>,
+}
+
+/// Runs forward, keeping what it was shown.
+struct WatchingBrain(Arc<Mutex<Vec<Seen>>>);
+
+impl Brain for WatchingBrain {
+    fn think(&mut self, observation: &Observation) -> TicCmd {
+        self.0.lock().unwrap().push(Seen {
+            map: observation.settings.map,
+            maketic: observation.maketic,
+            gametic: observation.gametic,
+            cmds: *observation.cmds,
+            console: observation
+                .console
+                .iter()
+                .map(|message| message.text.clone())
+                .collect(),
+        });
+        ForwardBrain.think(observation)
+    }
+}
+
+/// Moves as fast as the number of the tic it is asked for.
+struct CountingBrain;
+
+impl Brain for CountingBrain {
+    fn think(&mut self, observation: &Observation) -> TicCmd {
+        TicCmd {
+            forwardmove: observation.maketic as i8 + 1,
+            ..Default::default()
+        }
+    }
+}
+
+#[test]
+fn player_sends_its_commands() {
+    let mut desc = ServerDescription::new("Game server");
+    desc.start_game = Some(0);
+    let server = spawn_server(desc);
+    let wad = dummy_wad("player");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--player"]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    let game_data = wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    let syn = parse_syn(&syn.expect("bot did not connect"));
+    assert_eq!(syn.drone, 0, "bot joined as a drone");
+
+    let game_data = game_data.expect("bot sent no tics");
+    assert_eq!(first_tic(&game_data), (0, 1, Some(0x32)));
+}
+
+#[test]
+fn player_resends_requested_tics() {
+    let mut desc = ServerDescription::new("Lossy server");
+    desc.start_game = Some(0);
+    let server = spawn_server(desc);
+    let wad = dummy_wad("resend");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--player"]);
+
+    let mut later_tic = None;
+    while later_tic.is_none() {
+        let Some(data) = wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(10))
+        else {
+            break;
+        };
+        later_tic = Some(first_tic(&data).0).filter(|&start| start > 0);
+    }
+
+    let mut request = PACKET_TYPE_GAME_DATA_RESEND.to_be_bytes().to_vec();
+    request.extend_from_slice(&0i32.to_be_bytes());
+    request.push(1);
+    server.send_to_client(&request);
+
+    let mut resent = None;
+    while resent.is_none() {
+        let Some(data) = wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5))
+        else {
+            break;
+        };
+        resent = Some(first_tic(&data)).filter(|&(start, _, _)| start == 0);
+    }
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(later_tic.is_some(), "bot only sent its first tic");
+    assert_eq!(resent, Some((0, 1, Some(0x32))));
+}
+
+#[test]
+fn drone_sends_no_commands() {
+    let mut desc = ServerDescription::new("Game server");
+    desc.start_game = Some(-1);
+    let server = spawn_server(desc);
+    let wad = dummy_wad("drone");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap()]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    let game_data = wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(2));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "bot did not connect");
+    assert!(game_data.is_none(), "drone sent tics");
+}
+
+#[test]
+fn controller_launches_with_the_given_settings() {
+    let mut desc = ServerDescription::new("Bot match");
+    desc.controller = true;
+    desc.players = Arc::new(Mutex::new(vec!["HydraBot".to_string()]));
+    let server = spawn_server(desc);
+    let wad = dummy_wad("controller");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&[
+        "-a",
+        &address,
+        "-i",
+        wad.to_str().unwrap(),
+        "--player",
+        "--launch-players",
+        "1",
+        "--map",
+        "5",
+        "--skill",
+        "4",
+        "--fast",
+        "true",
+    ]);
+
+    let game_start = wait_for_packet(&server, PACKET_TYPE_GAME_START, Duration::from_secs(10));
+    let game_data = wait_for_packet(&server, PACKET_TYPE_GAME_DATA, Duration::from_secs(5));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    // ticdup, extratics, deathmatch, nomonsters, fast, respawn, episode, map,
+    // skill
+    let game_start = game_start.expect("bot did not start the game");
+    assert_eq!(&game_start[..9], &[1, 0, 0, 0, 1, 0, 1, 5, 3]);
+    assert!(game_data.is_some(), "bot did not play the game it started");
+}
+
+#[test]
+fn game_follows_the_negotiated_settings() {
+    let mut desc = ServerDescription::new("Duplicating server");
+    desc.start_game = Some(1);
+    desc.num_players = 2;
+    desc.ticdup = 2;
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let settings = client.get_settings().expect("game did not start");
+    let mut game = Game::new(
+        &settings,
+        clock.clone(),
+        Box::new(WatchingBrain(seen.clone())),
+    );
+    game.start_loop();
+
+    // Every command stands for two tics, so 7 tics' worth of time makes 3.
+    clock.advance(Duration::from_millis(200));
+    game.tick(&mut client);
+    let sent: Vec<_> = server
+        .received_of(PACKET_TYPE_GAME_DATA)
+        .iter()
+        .map(|data| first_tic(data).0)
+        .collect();
+    assert_eq!(sent, [0, 1, 2]);
+
+    let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0, 3]);
+    for _ in 0..3 {
+        // latency, players in game, then the diff of each player's command,
+        // ours included
+        data.extend_from_slice(&[0, 0, 0x03, 0x01, 0x19, 0x01, 0x7f]);
+    }
+    server.send_to_client(&data);
+    server.sync(&mut client);
+
+    clock.advance(Duration::from_millis(100));
+    game.tick(&mut client);
+    clock.advance(Duration::from_millis(100));
+    game.tick(&mut client);
+
+    let seen = seen.lock().unwrap();
+    let maketics: Vec<_> = seen.iter().map(|seen| seen.maketic).collect();
+    assert_eq!(maketics, [0, 1, 2, 3, 4, 5, 6]);
+
+    // We play as the second player, with our own commands rather than what
+    // the server sent for us.
+    let last = seen.last().unwrap();
+    assert_eq!(last.gametic, 6);
+    assert_eq!(last.cmds[0].forwardmove, 0x19);
+    assert_eq!(last.cmds[1].forwardmove, 0x32);
+}
+
+#[test]
+fn game_stays_close_to_the_server() {
+    let mut desc = ServerDescription::new("Slow server");
+    desc.start_game = Some(0);
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let settings = client.get_settings().expect("game did not start");
+    let mut game = Game::new(&settings, clock.clone(), Box::new(ForwardBrain));
+    game.start_loop();
+    let sent_tics = || -> Vec<u8> {
+        server
+            .received_of(PACKET_TYPE_GAME_DATA)
+            .iter()
+            .map(|data| first_tic(data).0)
+            .collect()
+    };
+
+    // A second's worth of tics, but no further than 8 ahead of the server.
+    clock.advance(Duration::from_secs(1));
+    game.tick(&mut client);
+    assert_eq!(sent_tics(), (0..=8).collect::<Vec<_>>());
+
+    clock.advance(Duration::from_secs(1));
+    game.tick(&mut client);
+    assert_eq!(sent_tics(), []);
+
+    // The server catching up lets the game move on.
+    server.send_to_client(&empty_tics(0, 4, 0));
+    server.sync(&mut client);
+    clock.advance(Duration::from_millis(200));
+    game.tick(&mut client);
+    clock.advance(Duration::from_millis(200));
+    game.tick(&mut client);
+    assert_eq!(sent_tics(), [9, 10, 11, 12]);
+}
+
+#[test]
+fn every_command_is_sent_as_the_tic_it_was_made_for() {
+    let mut desc = ServerDescription::new("Game server");
+    desc.start_game = Some(0);
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let settings = client.get_settings().expect("game did not start");
+    let mut game = Game::new(&settings, clock.clone(), Box::new(CountingBrain));
+    game.start_loop();
+    clock.advance(Duration::from_millis(200));
+    game.tick(&mut client);
+
+    let sent: Vec<_> = server
+        .received_of(PACKET_TYPE_GAME_DATA)
+        .iter()
+        .flat_map(|data| sent_tics(data))
+        .map(|tic| (tic.tic, tic.forwardmove))
+        .collect();
+    let made: Vec<_> = (0..7).map(|tic| (tic, Some(tic as i8 + 1))).collect();
+    assert_eq!(sent, made);
+}
+
+#[test]
+fn brain_sees_the_game() {
+    let mut desc = ServerDescription::new("Busy server");
+    desc.start_game = Some(0);
+    desc.num_players = 2;
+    let (server, mut client, clock) = join_virtual(desc, false);
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let settings = client.get_settings().expect("game did not start");
+    let mut game = Game::new(
+        &settings,
+        clock.clone(),
+        Box::new(WatchingBrain(seen.clone())),
+    );
+    game.start_loop();
+
+    server.send_to_client(&console_message("Welcome"));
+    let mut data = PACKET_TYPE_GAME_DATA.to_be_bytes().to_vec();
+    // seq, tic count, latency, players in game, then the diff of each
+    // player's command
+    data.extend_from_slice(&[0, 1, 0, 0, 0x03, 0x00, 0x01, 0x19]);
+    server.send_to_client(&data);
+    server.sync(&mut client);
+
+    clock.advance(Duration::from_millis(100));
+    game.tick(&mut client);
+    clock.advance(Duration::from_millis(100));
+    game.tick(&mut client);
+
+    let seen = seen.lock().unwrap();
+    assert!(seen.iter().all(|seen| seen.map == 1));
+
+    // Console messages are shown once, with the next command made.
+    assert!(seen[0].console.contains(&"Welcome".to_string()));
+    assert!(seen[1..].iter().all(|seen| seen.console.is_empty()));
+
+    let last = seen.last().unwrap();
+    assert_eq!(last.gametic, 1);
+    assert_eq!(last.cmds[1].forwardmove, 0x19);
+}
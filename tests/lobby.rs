@@ -0,0 +1,14 @@
+use hydra_bot::net::{Lobby, WaitData, NET_MAXPLAYERS};
+
+#[test]
+fn players_beyond_the_name_list_are_left_out() {
+    let mut wait_data = WaitData {
+        num_players: 200,
+        ..Default::default()
+    };
+    wait_data.player_names[0][..3].copy_from_slice(&['B', 'o', 't']);
+
+    let lobby = Lobby::from_wait_data(&wait_data);
+    assert_eq!(lobby.players.len(), NET_MAXPLAYERS);
+    assert_eq!(lobby.players[0].name, "Bot");
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+
+#[test]
+fn search_lists_servers_from_master() {
+    let servers: Vec<_> = ["First server", "Second server", "Third server"]
+        .into_iter()
+        .map(|name| spawn_server(ServerDescription::new(name)))
+        .collect();
+    let master = spawn_master(servers.iter().map(|server| server.addr).collect());
+    let wad = dummy_wad("master-list");
+
+    let output = run_bot(&[
+        "--search",
+        "--master",
+        &master.to_string(),
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "bot failed: {:?}", output);
+    for name in ["First server", "Second server", "Third server"] {
+        assert!(stdout.contains(name), "{} missing from:\n{}", name, stdout);
+    }
+}
+
+#[test]
+fn auto_join_applies_query_filters() {
+    let mut full = ServerDescription::new("Full server");
+    full.num_players = 4;
+    let full = spawn_server(full);
+
+    let mut other_mission = ServerDescription::new("Doom II server");
+    other_mission.gamemission = 1;
+    let other_mission = spawn_server(other_mission);
+
+    let mut one_slot = ServerDescription::new("Nearly full server");
+    one_slot.num_players = 3;
+    let one_slot = spawn_server(one_slot);
+
+    let good = spawn_server(ServerDescription::new("Good server"));
+
+    let master = spawn_master(vec![
+        full.addr,
+        other_mission.addr,
+        one_slot.addr,
+        good.addr,
+    ]);
+    let wad = dummy_wad("master-filter");
+
+    let mut bot = spawn_bot(&[
+        "--search",
+        "--auto-join",
+        "--free-slots",
+        "2",
+        "--master",
+        &master.to_string(),
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let syn = wait_for_packet(&good, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "bot never joined the matching server");
+    for server in [&full, &other_mission, &one_slot] {
+        assert!(server.packets.try_recv().is_err());
+    }
+}
+
+#[test]
+fn auto_join_skips_servers_with_other_wad() {
+    let mut other_wad = ServerDescription::new("Other WAD server");
+    other_wad.wad_sha1sum = [0xaa; 20];
+    let other_wad = spawn_server(other_wad);
+    let same_wad = spawn_server(ServerDescription::new("Same WAD server"));
+
+    let master = spawn_master(vec![other_wad.addr, same_wad.addr]);
+    let wad = dummy_wad("master-wad");
+
+    let mut bot = spawn_bot(&[
+        "--search",
+        "--auto-join",
+        "--match-wad",
+        "--master",
+        &master.to_string(),
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let syn = wait_for_packet(&same_wad, PACKET_TYPE_SYN, Duration::from_secs(15));
+    let keepalive = wait_for_packet(&same_wad, PACKET_TYPE_KEEPALIVE, Duration::from_secs(5));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(
+        syn.is_some(),
+        "bot never joined the server with the same WAD"
+    );
+    assert!(
+        keepalive.is_some(),
+        "bot did not stay on the server with the same WAD"
+    );
+
+    // Whether it tried the other server first depends on ping order, but if
+    // it did, it must have left again.
+    let packets: Vec<_> = other_wad.packets.try_iter().map(|(t, _)| t).collect();
+    if packets.contains(&PACKET_TYPE_SYN) {
+        assert!(packets.contains(&PACKET_TYPE_DISCONNECT), "{:?}", packets);
+    }
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+
+#[test]
+fn joins_server_behind_nat_through_hole_punch() {
+    let (server, master) = spawn_nat_server(ServerDescription::new("NAT server"));
+    let wad = dummy_wad("nat");
+
+    let mut bot = spawn_bot(&[
+        "-a",
+        &server.addr.to_string(),
+        "--master",
+        &master.to_string(),
+        "-i",
+        wad.to_str().unwrap(),
+    ]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    let keepalive = wait_for_packet(&server, PACKET_TYPE_KEEPALIVE, Duration::from_secs(5));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "no SYN got through the hole punch");
+    assert!(
+        keepalive.is_some(),
+        "bot did not stay connected after the punch"
+    );
+}
+
+#[test]
+fn no_hole_punch_without_master() {
+    let (server, _master) = spawn_nat_server(ServerDescription::new("NAT server"));
+    let wad = dummy_wad("nat-no-master");
+
+    let mut bot = spawn_bot(&["-a", &server.addr.to_string(), "-i", wad.to_str().unwrap()]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(4));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(
+        syn.is_none(),
+        "SYN reached a server behind NAT without a punch"
+    );
+}
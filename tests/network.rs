@@ -0,0 +1,133 @@
+mod common;
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use hydra_bot::net::{
+    resolve_address, resolve_address_with_port, DEFAULT_MASTER_PORT, DEFAULT_MASTER_SERVER,
+};
+
+use common::*;
+
+#[test]
+fn connects_to_ipv6_servers() {
+    let server = spawn_server_on("[::1]:0", ServerDescription::new("IPv6 server"));
+    let wad = dummy_wad("ipv6");
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap()]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "bot did not connect over IPv6");
+}
+
+#[test]
+fn resolves_hostnames() {
+    let server = spawn_server(ServerDescription::new("Named server"));
+    let wad = dummy_wad("hostname");
+
+    let address = format!("localhost:{}", server.addr.port());
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap()]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "bot did not connect to localhost");
+}
+
+#[test]
+fn sends_from_the_bound_address() {
+    let server = spawn_server(ServerDescription::new("Bound server"));
+    let wad = dummy_wad("bind");
+
+    // Find a free port to bind the bot to.
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let bind = format!("127.0.0.1:{}", port);
+
+    let address = server.addr.to_string();
+    let mut bot = spawn_bot(&["-a", &address, "-i", wad.to_str().unwrap(), "--bind", &bind]);
+
+    let syn = wait_for_packet(&server, PACKET_TYPE_SYN, Duration::from_secs(10));
+    bot.kill().unwrap();
+    bot.wait().unwrap();
+
+    assert!(syn.is_some(), "bot did not connect");
+    assert_eq!(server.client_addr().unwrap().to_string(), bind);
+}
+
+#[test]
+fn rejects_invalid_bind_addresses() {
+    let wad = dummy_wad("bad-bind");
+    let output = run_bot(&[
+        "-a",
+        "127.0.0.1",
+        "-i",
+        wad.to_str().unwrap(),
+        "--bind",
+        "nowhere",
+    ]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid bind address"));
+}
+
+#[test]
+fn rejections_are_not_retried() {
+    let mut desc = ServerDescription::new("Full server");
+    desc.reject = Some("Server is full!".to_string());
+    let server = spawn_server(desc);
+    let wad = dummy_wad("rejected");
+
+    let address = server.addr.to_string();
+    let output = run_bot(&["-a", &address, "-i", wad.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Connection rejected: Server is full!"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Retrying connection"), "{}", stdout);
+}
+
+#[test]
+fn addresses_are_resolved_for_the_local_socket() {
+    let v4: SocketAddr = "0.0.0.0:0".parse().unwrap();
+    let v6: SocketAddr = "[::1]:0".parse().unwrap();
+    let dual_stack: SocketAddr = "[::]:0".parse().unwrap();
+
+    let addr = resolve_address("localhost", v4).unwrap();
+    assert_eq!(addr, "127.0.0.1:2342".parse().unwrap());
+
+    // Brackets are only needed around IPv6 addresses with a port, but are
+    // fine without one.
+    let addr = resolve_address("[::1]", v6).unwrap();
+    assert_eq!(addr, "[::1]:2342".parse().unwrap());
+    let addr = resolve_address("[::1]:2343", dual_stack).unwrap();
+    assert_eq!(addr, "[::1]:2343".parse().unwrap());
+
+    // Dual-stack sockets reach both families, others only their own.
+    assert!(resolve_address("127.0.0.1", dual_stack).is_ok());
+    assert!(resolve_address("127.0.0.1", v6).is_err());
+    assert!(resolve_address("::1", v4).is_err());
+}
+
+#[test]
+fn master_servers_default_to_their_own_port() {
+    let v4: SocketAddr = "0.0.0.0:0".parse().unwrap();
+
+    let addr = resolve_address_with_port("localhost", DEFAULT_MASTER_PORT, v4).unwrap();
+    assert_eq!(addr, "127.0.0.1:27020".parse().unwrap());
+    let addr = resolve_address_with_port("localhost:2342", DEFAULT_MASTER_PORT, v4).unwrap();
+    assert_eq!(addr, "127.0.0.1:2342".parse().unwrap());
+    assert!(DEFAULT_MASTER_SERVER.ends_with(":27020"));
+}
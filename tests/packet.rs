@@ -0,0 +1,43 @@
+use hydra_bot::net::Protocol;
+use hydra_bot::Packet;
+
+#[test]
+fn protocols_are_written_as_a_list() {
+    let mut packet = Packet::new();
+    packet.write_protocol(Protocol::ChocolateDoom0).unwrap();
+    assert_eq!(packet.data(), b"\x01CHOCOLATE_DOOM_0\0");
+    assert_eq!(packet.read_protocol_list(), Protocol::ChocolateDoom0);
+}
+
+#[test]
+fn unknown_protocols_are_not_written() {
+    let mut packet = Packet::new();
+    assert!(packet.write_protocol(Protocol::Unknown).is_err());
+    assert!(packet.data().is_empty());
+}
+
+#[test]
+fn lobbies_with_too_many_players_are_not_read() {
+    // Players, drones, ready players, max players, controller, our slot.
+    let mut data = vec![9, 0, 9, 8, 0, 0];
+    for _ in 0..9 {
+        data.extend_from_slice(b"Player\0");
+        data.extend_from_slice(b"127.0.0.1\0");
+    }
+    data.extend_from_slice(&[0; 41]);
+
+    let mut packet = Packet::from_data(&data);
+    assert!(packet.read_wait_data().is_none());
+}
+
+#[test]
+fn games_with_too_many_players_are_not_read() {
+    // Everything up to the player count, then our slot and the classes.
+    let mut data = vec![
+        1, 0, 0, 0, 0, 0, 1, 1, 2, 0, 0, 1, 0, 0, 0, 0, 0xff, 0, 9, 0,
+    ];
+    data.extend_from_slice(&[0; 9]);
+
+    let mut packet = Packet::from_data(&data);
+    assert!(packet.read_settings().is_none());
+}
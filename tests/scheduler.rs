@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hydra_bot::clock::VirtualClock;
+use hydra_bot::scheduler::TicScheduler;
+
+const PERIOD: Duration = Duration::from_millis(10);
+
+fn scheduler() -> (TicScheduler, Arc<VirtualClock>) {
+    let clock = Arc::new(VirtualClock::new());
+    (TicScheduler::new(clock.clone(), 100), clock)
+}
+
+/// Polls until the scheduler has no tic due, returning how many were.
+fn due_tics(scheduler: &mut TicScheduler) -> u32 {
+    let mut tics = 0;
+    while scheduler.poll() {
+        tics += 1;
+    }
+    tics
+}
+
+#[test]
+fn tics_are_due_on_the_grid() {
+    let (mut scheduler, clock) = scheduler();
+
+    assert_eq!(scheduler.until_next_tic(), PERIOD);
+    assert_eq!(due_tics(&mut scheduler), 0);
+
+    // Waking up late does not push the next tic back.
+    clock.advance(Duration::from_millis(13));
+    assert_eq!(due_tics(&mut scheduler), 1);
+    assert_eq!(scheduler.until_next_tic(), Duration::from_millis(7));
+}
+
+#[test]
+fn short_stalls_are_caught_up() {
+    let (mut scheduler, clock) = scheduler();
+
+    // Two tics behind is still caught up on, one tic after the other.
+    clock.advance(Duration::from_millis(35));
+    assert_eq!(due_tics(&mut scheduler), 3);
+    assert_eq!(scheduler.get_stats().skipped_tics, 0);
+    assert_eq!(scheduler.until_next_tic(), Duration::from_millis(5));
+}
+
+#[test]
+fn long_stalls_are_skipped() {
+    let (mut scheduler, clock) = scheduler();
+
+    // Any further behind and the missed tics are dropped, rather than
+    // fired in a burst.
+    clock.advance(Duration::from_millis(105));
+    assert_eq!(due_tics(&mut scheduler), 1);
+    assert_eq!(scheduler.get_stats().skipped_tics, 9);
+    assert_eq!(scheduler.until_next_tic(), Duration::from_millis(5));
+}
+
+#[test]
+fn lateness_is_measured() {
+    let (mut scheduler, clock) = scheduler();
+
+    // Wakes up on the first tic, then 2ms later on every following one.
+    for millis in [10, 12, 12, 12] {
+        clock.advance(Duration::from_millis(millis));
+        assert!(scheduler.poll());
+    }
+
+    let stats = scheduler.get_stats();
+    assert_eq!(stats.tics, 4);
+    assert_eq!(stats.max_late, Duration::from_millis(6));
+    assert_eq!(stats.mean_late(), Duration::from_millis(3));
+    // The square root of the mean of 9, 1, 1 and 9.
+    assert_eq!(stats.stddev_late().as_micros(), 2236);
+}